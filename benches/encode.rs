@@ -0,0 +1,115 @@
+//! Compares the ways arrow batches are converted to tiberius rows on the delta tables in tests/data, run with
+//! `cargo bench`. All of them produce the same `TokenRow`s, the encoding into tds packets is done by tiberius
+//! and is not measured here
+use std::fs::File;
+use std::hint::black_box;
+
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lake2sql::arrow_convert::{
+    get_owned_columns, get_owned_token_rows, get_token_rows, RowEncoder,
+};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use tiberius::ColumnType;
+
+fn read_table(name: &str) -> Vec<RecordBatch> {
+    let dir = format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let mut files = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|x| x == "parquet").unwrap_or(false))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+        .into_iter()
+        .flat_map(|p| {
+            ParquetRecordBatchReaderBuilder::try_new(File::open(p).unwrap())
+                .unwrap()
+                .build()
+                .unwrap()
+                .map(|b| b.unwrap())
+        })
+        .collect()
+}
+
+/// The sql type a table created from this schema would have
+fn sql_type(dt: &DataType) -> ColumnType {
+    match dt {
+        DataType::Boolean => ColumnType::Bitn,
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => ColumnType::Intn,
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => ColumnType::Floatn,
+        DataType::Decimal128(_, _) => ColumnType::Decimaln,
+        DataType::Date32 | DataType::Date64 => ColumnType::Daten,
+        DataType::Time32(_) => ColumnType::Timen,
+        DataType::Timestamp(_, _) => ColumnType::Datetime2,
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            ColumnType::BigVarBin
+        }
+        _ => ColumnType::NVarchar,
+    }
+}
+
+fn encode(c: &mut Criterion) {
+    for table in ["delta-table", "faker", "user2"] {
+        let batches = read_table(table);
+        let collist = batches[0]
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| (f.name().to_string(), sql_type(f.data_type())))
+            .collect::<Vec<_>>();
+        let mut group = c.benchmark_group(table);
+        group.bench_function(BenchmarkId::new("token_rows", 1), |b| {
+            b.iter(|| {
+                for batch in batches.iter() {
+                    black_box(get_token_rows(batch, &collist, 1).unwrap());
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("token_rows", 4), |b| {
+            b.iter(|| {
+                for batch in batches.iter() {
+                    black_box(get_token_rows(batch, &collist, 4).unwrap());
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("owned_token_rows", 1), |b| {
+            b.iter(|| {
+                for batch in batches.iter() {
+                    black_box(get_owned_token_rows(batch, &collist, 1).unwrap());
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("owned_columns_row_encoder", 1), |b| {
+            b.iter(|| {
+                for batch in batches.iter() {
+                    let columns = get_owned_columns(batch, &collist, 1).unwrap();
+                    for row in RowEncoder::with_converted(batch, &collist, columns).unwrap() {
+                        black_box(row);
+                    }
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("row_encoder", 1), |b| {
+            b.iter(|| {
+                for batch in batches.iter() {
+                    for row in RowEncoder::new(batch, &collist).unwrap() {
+                        black_box(row);
+                    }
+                }
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
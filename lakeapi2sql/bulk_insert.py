@@ -1,5 +1,6 @@
 import inspect
-from typing import Awaitable, TypedDict
+from datetime import datetime, timezone
+from typing import Any, Awaitable, Callable, Literal, TypedDict
 import lakeapi2sql._lowlevel as lvd
 import pyarrow as pa
 from pyarrow.cffi import ffi as arrow_ffi
@@ -12,8 +13,104 @@ class BulkInfoField(TypedDict):
     arrow_type: str
 
 
-class BulkInfo(TypedDict):
+class BulkOptions(TypedDict, total=False):
+    check_constraints: bool
+    fire_triggers: bool
+    keep_nulls: bool
+    keep_identity: bool
+    table_lock: bool
+    order_hints: list[str]
+    batch_rows: int
+    parallelism: int
+    pipeline_depth: int
+    conversion_threads: int
+    command_timeout: float
+    http_read_timeout: float
+    load_timeout: float
+    max_retries: int
+    retry_backoff: float
+    retry_error_codes: list[int]
+
+
+class HttpAuth(TypedDict, total=False):
+    bearer_token: str
+    api_key: str
+    api_key_param: str
+    headers: dict[str, str]
+    client_cert: str
+    client_key: str
+    oauth2_token_url: str
+    oauth2_client_id: str
+    oauth2_client_secret: str
+    oauth2_scope: str
+
+
+class HttpPagination(TypedDict, total=False):
+    type: Literal["next_link", "cursor", "offset"]
+    header: str
+    param: str
+    limit_param: str
+    page_size: int
+
+
+class HttpRequestOptions(TypedDict, total=False):
+    method: Literal["GET", "POST", "PUT"]
+    params: dict[str, str | int | float]
+    headers: dict[str, str]
+    json: Any
+    proxy: str
+    ca_cert: str
+    verify_tls: bool
+    format: Literal["arrow_stream", "arrow_file", "parquet", "ndjson"]
+    pagination: HttpPagination
+
+
+class FileOptions(TypedDict, total=False):
+    format: Literal["parquet", "arrow_file", "arrow_stream", "csv", "ndjson"]
+    columns: list[str]
+    schema: pa.Schema
+    csv_delimiter: str
+    csv_quote: str
+    csv_has_header: bool
+    batch_size: int
+    filename_column: str
+
+
+class ProgressInfo(TypedDict):
+    rows_written: int
+    batches: int
+    bytes_received: int
+    elapsed_seconds: float
+
+
+class WatermarkInfo(TypedDict):
+    column: str
+    old: str | None
+    new: str | None
+
+
+class ColumnMappingInfo(TypedDict):
+    name: str
+    column_type: str
+    arrow_type: str | None
+
+
+class _BulkInfoOptional(TypedDict, total=False):
+    watermark: WatermarkInfo
+
+
+class BulkInfo(_BulkInfoOptional):
     fields: list[BulkInfoField]
+    metadata: dict[str, str]
+    columns: list[ColumnMappingInfo]
+    rows_read: int
+    rows_skipped: int
+    rows_written: int
+    batches: int
+    bytes_received: int
+    conversion_seconds: float
+    network_seconds: float
+    server_seconds: float
 
 
 async def insert_record_batch_to_sql(
@@ -22,22 +119,107 @@ async def insert_record_batch_to_sql(
     reader: pa.RecordBatchReader,
     col_names: list[str] | None = None,
     aad_token: str | None = None,
-):
+    bulk_options: BulkOptions | None = None,
+    progress_callback: Callable[[ProgressInfo], None] | None = None,
+) -> BulkInfo:
     connection_string, aad_token = await prepare_connection_string(connection_string, aad_token)
 
-    return await lvd.insert_arrow_reader_to_sql(connection_string, reader, table_name, col_names or [], aad_token)
+    return await lvd.insert_arrow_reader_to_sql(
+        connection_string, reader, table_name, col_names or [], aad_token, bulk_options, progress_callback
+    )
 
 
 async def insert_http_arrow_stream_to_sql(
     connection_string: str,
     table_name: str,
     url: str,
-    basic_auth: tuple[str, str],
+    basic_auth: tuple[str, str] | None,
     aad_token: str | None = None,
     col_names: list[str] | None = None,
+    watermark_column: str | None = None,
+    watermark_param: str | None = None,
+    bulk_options: BulkOptions | None = None,
+    progress_callback: Callable[[ProgressInfo], None] | None = None,
+    checkpoint_file: str | None = None,
+    checkpoint_skip_param: str | None = None,
+    http_auth: HttpAuth | None = None,
+    http_request: HttpRequestOptions | None = None,
 ) -> BulkInfo:
     connection_string, aad_token = await prepare_connection_string(connection_string, aad_token)
 
     return await lvd.insert_arrow_stream_to_sql(
-        connection_string, table_name, col_names or [], url, basic_auth[0], basic_auth[1], aad_token
+        connection_string,
+        table_name,
+        col_names or [],
+        url,
+        basic_auth[0] if basic_auth else None,
+        basic_auth[1] if basic_auth else None,
+        aad_token,
+        bulk_options,
+        watermark_column,
+        watermark_param,
+        progress_callback,
+        checkpoint_file,
+        checkpoint_skip_param,
+        http_auth,
+        http_request,
+    )
+
+
+async def insert_delta_to_sql(
+    connection_string: str,
+    table_name: str,
+    delta_path: str,
+    col_names: list[str] | None = None,
+    aad_token: str | None = None,
+    bulk_options: BulkOptions | None = None,
+    progress_callback: Callable[[ProgressInfo], None] | None = None,
+    version: int | None = None,
+    timestamp: datetime | str | None = None,
+) -> BulkInfo:
+    """Loads a local delta table. Use either version or timestamp for time travel, naive timestamps are in UTC"""
+    connection_string, aad_token = await prepare_connection_string(connection_string, aad_token)
+    timestamp_ms = None
+    if timestamp is not None:
+        if isinstance(timestamp, str):
+            timestamp = datetime.fromisoformat(timestamp)
+        if timestamp.tzinfo is None:
+            timestamp = timestamp.replace(tzinfo=timezone.utc)
+        timestamp_ms = int(timestamp.timestamp() * 1000)
+
+    return await lvd.insert_delta_to_sql(
+        connection_string,
+        delta_path,
+        table_name,
+        col_names or [],
+        aad_token,
+        bulk_options,
+        progress_callback,
+        version,
+        timestamp_ms,
+    )
+
+
+async def insert_files_to_sql(
+    connection_string: str,
+    table_name: str,
+    path: str,
+    col_names: list[str] | None = None,
+    aad_token: str | None = None,
+    bulk_options: BulkOptions | None = None,
+    progress_callback: Callable[[ProgressInfo], None] | None = None,
+    file_options: FileOptions | None = None,
+) -> BulkInfo:
+    """Loads local files, path can be a glob pattern like data/**/*.parquet"""
+    connection_string, aad_token = await prepare_connection_string(connection_string, aad_token)
+
+    return await lvd.insert_files_to_sql(
+        connection_string,
+        path,
+        table_name,
+        col_names or [],
+        aad_token,
+        bulk_options,
+        progress_callback,
+        file_options,
     )
@@ -1,6 +1,7 @@
 import lakeapi2sql._lowlevel as lvd
+from lakeapi2sql.bulk_insert import BulkInfo, BulkOptions, HttpAuth, HttpRequestOptions, ProgressInfo
 from lakeapi2sql.utils import prepare_connection_string
-from typing import TypedDict
+from typing import Any, Callable, Literal, TypedDict
 
 
 class TdsColumn(TypedDict):
@@ -10,7 +11,56 @@ class TdsColumn(TypedDict):
 
 class TdsResult(TypedDict):
     columns: list[TdsColumn]
-    rows: list[dict]
+    rows: list[tuple[Any, ...]] | list[dict[str, Any]]
+
+
+class ExportField(TypedDict):
+    name: str
+    arrow_type: str
+
+
+class ExportResult(TypedDict):
+    fields: list[ExportField]
+    metadata: dict[str, str]
+    rows: int
+    files: list[str]
+    bytes: int
+
+
+class ParquetExportOptions(TypedDict, total=False):
+    batch_rows: int
+    row_group_rows: int
+    compression: Literal["none", "snappy", "gzip", "lz4", "zstd"]
+    compression_level: int
+    max_rows_per_file: int
+    max_bytes_per_file: int
+
+
+class DeltaExportOptions(ParquetExportOptions, total=False):
+    mode: Literal["append", "overwrite"]
+    partition_by: list[str]
+
+
+class ArrowExportOptions(TypedDict, total=False):
+    batch_rows: int
+    format: Literal["arrow_stream", "arrow_file"]
+    compression: Literal["none", "lz4", "zstd"]
+
+
+class CsvExportOptions(TypedDict, total=False):
+    batch_rows: int
+    delimiter: str
+    row_terminator: str
+    quote: str
+    quoting: Literal["minimal", "all", "none"]
+    null: str
+    header: bool
+    date_format: str
+    datetime_format: str
+    datetimeoffset_format: str
+    time_format: str
+    encoding: str | int
+    format_file: bool | str
 
 
 class TdsConnection:
@@ -31,6 +81,115 @@ class TdsConnection:
         return await lvd.execute_sql(self._connection, sql, arguments or [])
 
     async def execute_sql_with_result(
-        self, sql: str, arguments: list[str | int | float | bool | None] | None = None
+        self, sql: str, arguments: list[str | int | float | bool | None] | None = None, as_dict: bool = False
     ) -> TdsResult:
-        return await lvd.execute_sql_with_result(self._connection, sql, arguments or [])
+        """Runs the query and returns the rows of the first result as tuples, or as dicts keyed by column name
+        with as_dict. Dates and times become datetime objects (datetimeoffset with its offset as tzinfo),
+        decimals decimal.Decimal and uniqueidentifiers uuid.UUID"""
+        return await lvd.execute_sql_with_result(self._connection, sql, arguments or [], as_dict)
+
+    async def copy_query(
+        self,
+        sql: str,
+        target: "TdsConnection | str",
+        table_name: str,
+        arguments: list[str | int | float | bool | None] | None = None,
+        col_names: list[str] | None = None,
+        aad_token: str | None = None,
+        bulk_options: BulkOptions | None = None,
+        progress_callback: Callable[[ProgressInfo], None] | None = None,
+        create_table: bool = False,
+    ) -> BulkInfo:
+        """Bulk loads the result of the query into a table of the target, which is a TdsConnection or a
+        connection string. The rows are streamed in batches, so the result does not have to fit into memory.
+        With create_table, a missing target table is created with the column types of the query"""
+        if isinstance(target, TdsConnection):
+            connection_string, aad_token = target._connection_string, target._aad_token
+        else:
+            connection_string = target
+        connection_string, aad_token = await prepare_connection_string(connection_string, aad_token)
+        return await lvd.copy_query_to_sql(
+            self._connection,
+            sql,
+            arguments or [],
+            connection_string,
+            table_name,
+            col_names or [],
+            aad_token,
+            bulk_options,
+            progress_callback,
+            create_table,
+        )
+
+    async def copy_table(
+        self,
+        source_table: str,
+        target: "TdsConnection | str",
+        table_name: str | None = None,
+        col_names: list[str] | None = None,
+        aad_token: str | None = None,
+        bulk_options: BulkOptions | None = None,
+        progress_callback: Callable[[ProgressInfo], None] | None = None,
+        create_table: bool = False,
+    ) -> BulkInfo:
+        """Copies a table to the target, into a table of the same name unless table_name is given"""
+        columns = ", ".join("[" + c.replace("]", "]]") + "]" for c in col_names) if col_names else "*"
+        return await self.copy_query(
+            f"SELECT {columns} FROM {source_table}",
+            target,
+            table_name or source_table,
+            col_names=col_names,
+            aad_token=aad_token,
+            bulk_options=bulk_options,
+            progress_callback=progress_callback,
+            create_table=create_table,
+        )
+
+    async def export_query_to_parquet(
+        self,
+        sql: str,
+        path: str,
+        arguments: list[str | int | float | bool | None] | None = None,
+        options: ParquetExportOptions | None = None,
+    ) -> ExportResult:
+        """Streams the result of the query into a parquet file. With max_rows_per_file or max_bytes_per_file
+        the result is split into files named like path, with a counter: data.parquet becomes data-00000.parquet,
+        data-00001.parquet and so on"""
+        return await lvd.export_query_to_parquet(self._connection, sql, arguments or [], path, options)
+
+    async def export_query_to_arrow(
+        self,
+        sql: str,
+        target: str,
+        arguments: list[str | int | float | bool | None] | None = None,
+        options: ArrowExportOptions | None = None,
+        http_auth: HttpAuth | None = None,
+        http_request: HttpRequestOptions | None = None,
+    ) -> ExportResult:
+        """Writes the result of the query as Arrow IPC stream or file. The target is a local path, - for stdout
+        or an http(s) url, which gets the data as body of a PUT request (set method in http_request for a POST)"""
+        return await lvd.export_query_to_arrow(
+            self._connection, sql, arguments or [], target, options, http_auth, http_request
+        )
+
+    async def export_query_to_csv(
+        self,
+        sql: str,
+        path: str,
+        arguments: list[str | int | float | bool | None] | None = None,
+        options: CsvExportOptions | None = None,
+    ) -> ExportResult:
+        """Writes the result of the query as csv file that bcp and BULK INSERT can read. With format_file, a bcp
+        format file is written next to it (or to the given path) and listed in the files of the result"""
+        return await lvd.export_query_to_csv(self._connection, sql, arguments or [], path, options)
+
+    async def export_query_to_delta(
+        self,
+        sql: str,
+        table_path: str,
+        arguments: list[str | int | float | bool | None] | None = None,
+        options: DeltaExportOptions | None = None,
+    ) -> ExportResult:
+        """Writes the result of the query into a local delta table, which is created if it does not exist.
+        The data files are only committed to the _delta_log once the whole query was read"""
+        return await lvd.export_query_to_delta(self._connection, sql, arguments or [], table_path, options)
@@ -1,4 +1,5 @@
 use arrow::array::Array;
+use arrow::array::ArrowPrimitiveType;
 use arrow::array::BinaryArray;
 use arrow::array::BooleanArray;
 use arrow::array::Date32Array;
@@ -14,8 +15,11 @@ use arrow::array::Int64Array;
 use arrow::array::Int8Array;
 use arrow::array::LargeBinaryArray;
 use arrow::array::LargeStringArray;
+use arrow::array::PrimitiveArray;
 use arrow::array::StringArray;
+use arrow::array::Time32MillisecondArray;
 use arrow::array::Time32SecondArray;
+use arrow::array::Time64MicrosecondArray;
 use arrow::array::TimestampMicrosecondArray;
 use arrow::array::TimestampMillisecondArray;
 use arrow::array::TimestampNanosecondArray;
@@ -28,15 +32,16 @@ use arrow::record_batch::RecordBatch;
 use rust_decimal::prelude::*;
 use std::borrow::Cow;
 
-use std::time::Duration as StdDuration;
 use tiberius::numeric::Numeric;
 use tiberius::time::time::Date;
+use tiberius::time::time::Duration as TimeDuration;
 use tiberius::time::time::PrimitiveDateTime;
 use tiberius::time::time::Time;
 use tiberius::ColumnData;
 use tiberius::ColumnType;
 use tiberius::ToSql;
 use tiberius::TokenRow;
+use tiberius::Uuid;
 
 use crate::error::LakeApi2SqlError;
 
@@ -45,38 +50,137 @@ fn to_col_dt<'a>(d: ColumnData<'_>) -> ColumnData<'a> {
         ColumnData::DateTime2(dt) => ColumnData::DateTime2(dt),
         ColumnData::DateTime(dt) => ColumnData::DateTime(dt),
         ColumnData::Time(dt) => ColumnData::Time(dt),
+        ColumnData::DateTimeOffset(dt) => ColumnData::DateTimeOffset(dt),
         _ => panic!("Not a time field"),
     }
 }
 
-pub(crate) fn get_token_rows<'a, 'b>(
-    batch: &'a RecordBatch,
-    colsnames: &'b Vec<(String, ColumnType)>,
-) -> Result<Vec<TokenRow<'a>>, LakeApi2SqlError> {
-    let unix_min_date =
-        Date::from_calendar_date(1970, tiberius::time::time::Month::January, 1).unwrap();
-    let sql_min_date =
-        Date::from_calendar_date(1, tiberius::time::time::Month::January, 1).unwrap();
-    let sql_min_datetime =
-        Date::from_calendar_date(1900, tiberius::time::time::Month::January, 1).unwrap();
-    let unix_min: PrimitiveDateTime = unix_min_date.with_time(Time::from_hms(0, 0, 0).unwrap());
-    let sql_min_to_unix_min = (unix_min_date - sql_min_date).whole_days();
-    let sql_min_dt_to_unix_min = (unix_min_date - sql_min_datetime).whole_days();
-    let rows = batch.num_rows();
-    //let mut token_rows: Vec<TokenRow> = vec![TokenRow::new(); rows.try_into()?];
-    let mut token_rows: Vec<TokenRow<'a>> = Vec::with_capacity(rows);
-    for _ in 0..rows {
-        token_rows.push(TokenRow::with_capacity(colsnames.len()));
+fn get<T: ArrowPrimitiveType>(ba: &PrimitiveArray<T>, i: usize) -> Option<T::Native> {
+    if ba.is_null(i) {
+        None
+    } else {
+        Some(ba.value(i))
+    }
+}
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+#[derive(Clone, Copy)]
+struct DateOffsets {
+    unix_min: PrimitiveDateTime,
+    sql_min_to_unix_min: i64,
+    sql_min_dt_to_unix_min: i64,
+}
+
+impl DateOffsets {
+    fn new() -> Self {
+        let unix_min_date =
+            Date::from_calendar_date(1970, tiberius::time::time::Month::January, 1).unwrap();
+        let sql_min_date =
+            Date::from_calendar_date(1, tiberius::time::time::Month::January, 1).unwrap();
+        let sql_min_datetime =
+            Date::from_calendar_date(1900, tiberius::time::time::Month::January, 1).unwrap();
+        Self {
+            unix_min: unix_min_date.with_time(Time::from_hms(0, 0, 0).unwrap()),
+            sql_min_to_unix_min: (unix_min_date - sql_min_date).whole_days(),
+            sql_min_dt_to_unix_min: (unix_min_date - sql_min_datetime).whole_days(),
+        }
+    }
+
+    /// Milliseconds since the unix epoch, None if out of range
+    fn timestamp_ms(&self, ms: i64) -> Option<PrimitiveDateTime> {
+        self.unix_min.checked_add(TimeDuration::milliseconds(ms))
+    }
+
+    /// Microseconds since the unix epoch, None if out of range
+    fn timestamp_us(&self, us: i64) -> Option<PrimitiveDateTime> {
+        self.unix_min.checked_add(TimeDuration::microseconds(us))
+    }
+
+    /// Nanoseconds since the unix epoch, None if out of range
+    fn timestamp_ns(&self, ns: i64) -> Option<PrimitiveDateTime> {
+        self.unix_min.checked_add(TimeDuration::nanoseconds(ns))
+    }
+
+    /// datetime counts days since 1900 and 1/300 seconds
+    fn sql_datetime(&self, us: i64) -> tiberius::time::DateTime {
+        let us = us + self.sql_min_dt_to_unix_min * MICROS_PER_DAY;
+        let mut days = us.div_euclid(MICROS_PER_DAY);
+        let mut fragments = (us.rem_euclid(MICROS_PER_DAY) * 300 + 500_000) / 1_000_000;
+        if fragments == 300 * 86_400 {
+            days += 1;
+            fragments = 0;
+        }
+        tiberius::time::DateTime::new(days as i32, fragments as u32)
+    }
+
+    /// smalldatetime counts days since 1900 and minutes
+    fn sql_smalldatetime(&self, us: i64) -> tiberius::time::SmallDateTime {
+        let us = us + self.sql_min_dt_to_unix_min * MICROS_PER_DAY;
+        let mut days = us.div_euclid(MICROS_PER_DAY);
+        let mut minutes = (us.rem_euclid(MICROS_PER_DAY) + 30_000_000) / 60_000_000;
+        if minutes == 24 * 60 {
+            days += 1;
+            minutes = 0;
+        }
+        tiberius::time::SmallDateTime::new(days as u16, minutes as u16)
     }
-    for (colname, coltype) in colsnames {
+}
+
+/// An arrow column together with the sql type it is converted to
+enum ColumnEncoder<'a> {
+    Missing,
+    NullString,
+    NullDateTime,
+    NullBit,
+    NullI32,
+    Bit(&'a BooleanArray),
+    I32(&'a Int32Array),
+    Utf8(&'a StringArray),
+    LargeUtf8(&'a LargeStringArray),
+    TimestampMs(&'a TimestampMillisecondArray),
+    TimestampUs(&'a TimestampMicrosecondArray),
+    TimestampUsAsDateTime(&'a TimestampMicrosecondArray),
+    TimestampUsAsSmallDateTime(&'a TimestampMicrosecondArray),
+    TimestampUsAsDateTimeOffset(&'a TimestampMicrosecondArray),
+    TimestampNs(&'a TimestampNanosecondArray),
+    U8(&'a UInt8Array),
+    I8AsI16(&'a Int8Array),
+    I8AsU8(&'a Int8Array),
+    I16(&'a Int16Array),
+    I64(&'a Int64Array),
+    U16(&'a UInt16Array),
+    U32(&'a UInt32Array),
+    U64(&'a UInt64Array),
+    F16(&'a Float16Array),
+    F32(&'a Float32Array),
+    F64AsI32(&'a Float64Array),
+    F64(&'a Float64Array),
+    Date32AsDateTime(&'a Date32Array),
+    Date32(&'a Date32Array),
+    Date64(&'a Date64Array),
+    Time32Second(&'a Time32SecondArray),
+    Time32Millisecond(&'a Time32MillisecondArray),
+    Time64Microsecond(&'a Time64MicrosecondArray),
+    Binary(&'a BinaryArray),
+    LargeBinary(&'a LargeBinaryArray),
+    FixedSizeBinary(&'a FixedSizeBinaryArray),
+    FixedSizeBinaryAsGuid(&'a FixedSizeBinaryArray),
+    Numeric(&'a Decimal128Array, u8),
+    DecimalAsF64(&'a Decimal128Array, u8),
+}
+
+impl<'a> ColumnEncoder<'a> {
+    fn new(
+        batch: &'a RecordBatch,
+        colname: &str,
+        coltype: &ColumnType,
+    ) -> Result<Self, LakeApi2SqlError> {
         let mightcol = batch.column_by_name(colname);
 
         if mightcol.is_none() {
             log::debug!("colname: {}. Not found", colname);
-            for rowindex in 0..rows {
-                token_rows[rowindex].push(ColumnData::String(None));
-            }
-            continue;
+            return Ok(ColumnEncoder::Missing);
         }
         let col = mightcol.unwrap();
         log::debug!(
@@ -85,108 +189,53 @@ pub(crate) fn get_token_rows<'a, 'b>(
             col.data_type(),
             coltype
         );
+        let any = col.as_any();
         //For docs: col.data_type().to_physical_type()
-        match col.data_type() {
-            arrow::datatypes::DataType::Boolean => {
-                assert!(coltype == &ColumnType::Bit || coltype == &ColumnType::Bitn);
-                let ba = col.as_any().downcast_ref::<BooleanArray>().unwrap();
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::Bit(val));
-                    rowindex += 1;
+        Ok(match col.data_type() {
+            arrow::datatypes::DataType::Boolean => match coltype {
+                ColumnType::Bit | ColumnType::Bitn => {
+                    ColumnEncoder::Bit(any.downcast_ref::<BooleanArray>().unwrap())
                 }
-            }
-            arrow::datatypes::DataType::Int32 => {
-                assert!(coltype == &ColumnType::Int4 || coltype == &ColumnType::Intn);
-                let ba = col.as_any().downcast_ref::<Int32Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I32(val));
-                    rowindex += 1;
+                _ => {
+                    return Err(LakeApi2SqlError::NotSupported {
+                        dtype: col.data_type().clone(),
+                        column_type: *coltype,
+                    })
                 }
-            }
-            arrow::datatypes::DataType::Utf8 => {
-                let ba = col.as_any().downcast_ref::<StringArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::String(val.map(Cow::from)));
-                    rowindex += 1;
+            },
+            arrow::datatypes::DataType::Int32 => match coltype {
+                ColumnType::Int4 | ColumnType::Intn => {
+                    ColumnEncoder::I32(any.downcast_ref::<Int32Array>().unwrap())
                 }
+                _ => {
+                    return Err(LakeApi2SqlError::NotSupported {
+                        dtype: col.data_type().clone(),
+                        column_type: *coltype,
+                    })
+                }
+            },
+            arrow::datatypes::DataType::Utf8 => {
+                ColumnEncoder::Utf8(any.downcast_ref::<StringArray>().unwrap())
             }
             arrow::datatypes::DataType::LargeUtf8 => {
-                let ba = col.as_any().downcast_ref::<LargeStringArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::String(val.map(Cow::from)));
-                    rowindex += 1;
-                }
+                ColumnEncoder::LargeUtf8(any.downcast_ref::<LargeStringArray>().unwrap())
             }
             arrow::datatypes::DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, _) => {
-                let ba = col
-                    .as_any()
-                    .downcast_ref::<TimestampMillisecondArray>()
-                    .unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            if vs < 0 {
-                                ColumnData::DateTime2(None)
-                            } else {
-                                let pt = unix_min + StdDuration::from_millis(vs as u64);
-                                to_col_dt(pt.to_sql())
-                            }
-                        }
-                        None => ColumnData::DateTime2(None),
-                    });
-                    rowindex += 1;
-                }
+                ColumnEncoder::TimestampMs(any.downcast_ref::<TimestampMillisecondArray>().unwrap())
             }
             arrow::datatypes::DataType::Timestamp(arrow::datatypes::TimeUnit::Microsecond, _) => {
-                let ba = col
-                    .as_any()
-                    .downcast_ref::<TimestampMicrosecondArray>()
-                    .unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            if vs < 0 {
-                                ColumnData::DateTime2(None)
-                            } else {
-                                to_col_dt((unix_min + StdDuration::from_micros(vs as u64)).to_sql())
-                            }
-                        }
-                        None => ColumnData::DateTime2(None),
-                    });
-                    rowindex += 1;
+                let ba = any.downcast_ref::<TimestampMicrosecondArray>().unwrap();
+                match coltype {
+                    ColumnType::Datetime | ColumnType::Datetimen => {
+                        ColumnEncoder::TimestampUsAsDateTime(ba)
+                    }
+                    ColumnType::Datetime4 => ColumnEncoder::TimestampUsAsSmallDateTime(ba),
+                    ColumnType::DatetimeOffsetn => ColumnEncoder::TimestampUsAsDateTimeOffset(ba),
+                    _ => ColumnEncoder::TimestampUs(ba),
                 }
             }
             arrow::datatypes::DataType::Timestamp(arrow::datatypes::TimeUnit::Nanosecond, _) => {
-                let ba = col
-                    .as_any()
-                    .downcast_ref::<TimestampNanosecondArray>()
-                    .unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            if vs < 0 {
-                                ColumnData::DateTime2(None)
-                            } else {
-                                to_col_dt((unix_min + StdDuration::from_nanos(vs as u64)).to_sql())
-                            }
-                        }
-                        None => ColumnData::DateTime2(None),
-                    });
-                    rowindex += 1;
-                }
+                ColumnEncoder::TimestampNs(any.downcast_ref::<TimestampNanosecondArray>().unwrap())
             }
             arrow::datatypes::DataType::Null => {
                 if coltype == &ColumnType::BigVarChar
@@ -196,285 +245,104 @@ pub(crate) fn get_token_rows<'a, 'b>(
                     || coltype == &ColumnType::BigChar
                     || coltype == &ColumnType::NText
                 {
-                    for rowindex in 0..rows {
-                        token_rows[rowindex].push(ColumnData::String(None));
-                    }
+                    ColumnEncoder::NullString
                 } else if coltype == &ColumnType::Datetime
                     || coltype == &ColumnType::Datetimen
                     || coltype == &ColumnType::Datetime4
                 {
-                    for rowindex in 0..rows {
-                        token_rows[rowindex].push(ColumnData::DateTime(None));
-                    }
+                    ColumnEncoder::NullDateTime
                 } else if coltype == &ColumnType::Bit || coltype == &ColumnType::Bitn {
-                    for rowindex in 0..rows {
-                        token_rows[rowindex].push(ColumnData::Bit(None));
-                    }
+                    ColumnEncoder::NullBit
                 } else {
-                    for rowindex in 0..rows {
-                        token_rows[rowindex].push(ColumnData::I32(None));
-                    }
+                    ColumnEncoder::NullI32
                 }
             }
             arrow::datatypes::DataType::UInt8 => {
-                let ba = col.as_any().downcast_ref::<UInt8Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::U8(val));
-                    rowindex += 1;
-                }
+                ColumnEncoder::U8(any.downcast_ref::<UInt8Array>().unwrap())
             }
             arrow::datatypes::DataType::Int8 => {
-                let ba = col.as_any().downcast_ref::<Int8Array>().unwrap();
+                let ba = any.downcast_ref::<Int8Array>().unwrap();
                 if coltype == &ColumnType::Int2 {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(ColumnData::I16(val.map(|v| v as i16)));
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::I8AsI16(ba)
                 } else {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(ColumnData::U8(val.map(|v| v as u8)));
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::I8AsU8(ba)
                 }
             }
             arrow::datatypes::DataType::Int16 => {
-                let ba = col.as_any().downcast_ref::<Int16Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I16(val));
-                    rowindex += 1;
-                }
+                ColumnEncoder::I16(any.downcast_ref::<Int16Array>().unwrap())
             }
             arrow::datatypes::DataType::Int64 => {
-                let ba = col.as_any().downcast_ref::<Int64Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I64(val));
-                    rowindex += 1;
-                }
+                ColumnEncoder::I64(any.downcast_ref::<Int64Array>().unwrap())
             }
             arrow::datatypes::DataType::UInt16 => {
-                let ba = col.as_any().downcast_ref::<UInt16Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I32(val.map(|x| x.into())));
-                    rowindex += 1;
-                }
+                ColumnEncoder::U16(any.downcast_ref::<UInt16Array>().unwrap())
             }
             arrow::datatypes::DataType::UInt32 => {
-                let ba = col.as_any().downcast_ref::<UInt32Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I64(val.map(|x| x.into())));
-                    rowindex += 1;
-                }
+                ColumnEncoder::U32(any.downcast_ref::<UInt32Array>().unwrap())
             }
             arrow::datatypes::DataType::UInt64 => {
-                let ba = col.as_any().downcast_ref::<UInt64Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::I64(val.map(|x| x as i64)));
-                    rowindex += 1;
-                }
+                ColumnEncoder::U64(any.downcast_ref::<UInt64Array>().unwrap())
             }
             arrow::datatypes::DataType::Float16 => {
-                let ba = col.as_any().downcast_ref::<Float16Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::F32(val.map(|x| x.to_f32())));
-                    rowindex += 1;
-                }
+                ColumnEncoder::F16(any.downcast_ref::<Float16Array>().unwrap())
             }
             arrow::datatypes::DataType::Float32 => {
-                let ba = col.as_any().downcast_ref::<Float32Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::F32(val));
-                    rowindex += 1;
-                }
+                ColumnEncoder::F32(any.downcast_ref::<Float32Array>().unwrap())
             }
             arrow::datatypes::DataType::Float64 => {
-                let ba = col.as_any().downcast_ref::<Float64Array>().unwrap();
+                let ba = any.downcast_ref::<Float64Array>().unwrap();
                 if coltype == &ColumnType::Int4 || coltype == &ColumnType::Intn {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(ColumnData::I32(val.map(|v| v as i32)));
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::F64AsI32(ba)
                 } else {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(ColumnData::F64(val));
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::F64(ba)
                 }
             }
             arrow::datatypes::DataType::Date32 => {
-                let ba = col.as_any().downcast_ref::<Date32Array>().unwrap();
+                let ba = any.downcast_ref::<Date32Array>().unwrap();
                 if coltype == &ColumnType::Datetime || coltype == &ColumnType::Datetimen {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(match val {
-                            Some(vs) => {
-                                let days = sql_min_dt_to_unix_min + (vs as i64);
-                                ColumnData::DateTime(Some(tiberius::time::DateTime::new(
-                                    days.try_into().unwrap(),
-                                    0,
-                                )))
-                            }
-                            None => ColumnData::DateTime(None),
-                        });
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::Date32AsDateTime(ba)
                 } else {
-                    let mut rowindex = 0;
-                    for val in ba.iter() {
-                        token_rows[rowindex].push(match val {
-                            Some(vs) => {
-                                let days = sql_min_to_unix_min + (vs as i64);
-                                ColumnData::Date(Some(tiberius::time::Date::new(
-                                    days.try_into().unwrap(),
-                                )))
-                            }
-                            None => ColumnData::Date(None),
-                        });
-                        rowindex += 1;
-                    }
+                    ColumnEncoder::Date32(ba)
                 }
             }
             arrow::datatypes::DataType::Date64 => {
-                let ba = col.as_any().downcast_ref::<Date64Array>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            let days = sql_min_to_unix_min + vs;
-                            ColumnData::Date(Some(tiberius::time::Date::new(
-                                days.try_into().unwrap(),
-                            )))
-                        }
-                        None => ColumnData::Date(None),
-                    });
-                    rowindex += 1;
-                }
+                ColumnEncoder::Date64(any.downcast_ref::<Date64Array>().unwrap())
             }
             arrow::datatypes::DataType::Time32(arrow::datatypes::TimeUnit::Second) => {
-                let ba = col.as_any().downcast_ref::<Time32SecondArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            if vs < 0 {
-                                ColumnData::Time(None)
-                            } else {
-                                to_col_dt(
-                                    Time::from_hms(
-                                        // TODO: Testing
-                                        (vs / 60 / 60).try_into().unwrap(),
-                                        ((vs / 60) % 60).try_into().unwrap(),
-                                        (vs % 60).try_into().unwrap(),
-                                    )
-                                    .unwrap()
-                                    .to_sql(),
-                                )
-                            }
-                        }
-                        None => ColumnData::Time(None),
-                    });
-                    rowindex += 1;
-                }
+                ColumnEncoder::Time32Second(any.downcast_ref::<Time32SecondArray>().unwrap())
             }
             arrow::datatypes::DataType::Time32(arrow::datatypes::TimeUnit::Millisecond) => {
-                let ba = col.as_any().downcast_ref::<Time32SecondArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(match val {
-                        Some(vs) => {
-                            if vs < 0 {
-                                ColumnData::Time(None)
-                            } else {
-                                to_col_dt(
-                                    Time::from_hms_milli(
-                                        (vs / 1000 / 60 / 60).try_into().unwrap(),
-                                        ((vs / 1000 / 60) % 60).try_into().unwrap(),
-                                        ((vs / 1000) % 60).try_into().unwrap(),
-                                        (vs % 1000).try_into().unwrap(),
-                                    )
-                                    .unwrap()
-                                    .to_sql(),
-                                )
-                            }
-                        }
-
-                        None => ColumnData::Time(None),
-                    });
-                    rowindex += 1;
-                }
+                ColumnEncoder::Time32Millisecond(
+                    any.downcast_ref::<Time32MillisecondArray>().unwrap(),
+                )
+            }
+            arrow::datatypes::DataType::Time64(arrow::datatypes::TimeUnit::Microsecond) => {
+                ColumnEncoder::Time64Microsecond(
+                    any.downcast_ref::<Time64MicrosecondArray>().unwrap(),
+                )
             }
             arrow::datatypes::DataType::Binary => {
-                let ba = col.as_any().downcast_ref::<BinaryArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::Binary(val.map(Cow::from)));
-                    rowindex += 1;
-                }
+                ColumnEncoder::Binary(any.downcast_ref::<BinaryArray>().unwrap())
             }
             arrow::datatypes::DataType::LargeBinary => {
-                let ba = col.as_any().downcast_ref::<LargeBinaryArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::Binary(val.map(Cow::from)));
-                    rowindex += 1;
-                }
+                ColumnEncoder::LargeBinary(any.downcast_ref::<LargeBinaryArray>().unwrap())
             }
-            arrow::datatypes::DataType::FixedSizeBinary(_) => {
-                let ba = col.as_any().downcast_ref::<FixedSizeBinaryArray>().unwrap();
-
-                let mut rowindex = 0;
-                for val in ba.iter() {
-                    token_rows[rowindex].push(ColumnData::Binary(val.map(Cow::from)));
-                    rowindex += 1;
+            arrow::datatypes::DataType::FixedSizeBinary(n) => {
+                let ba = any.downcast_ref::<FixedSizeBinaryArray>().unwrap();
+                if *n == 16 && coltype == &ColumnType::Guid {
+                    ColumnEncoder::FixedSizeBinaryAsGuid(ba)
+                } else {
+                    ColumnEncoder::FixedSizeBinary(ba)
                 }
             }
             arrow::datatypes::DataType::Decimal128(_, s) => {
-                let ba = col.as_any().downcast_ref::<Decimal128Array>().unwrap();
+                let ba = any.downcast_ref::<Decimal128Array>().unwrap();
                 let scale: u8 = (*s).try_into().unwrap();
-                let mut rowindex = 0;
                 match coltype {
                     ColumnType::Numericn | ColumnType::Decimaln => {
-                        for val in ba.iter() {
-                            token_rows[rowindex].push(ColumnData::Numeric(
-                                val.map(|x| Numeric::new_with_scale(x, scale)),
-                            ));
-                            rowindex += 1;
-                        }
-                    }
-                    ColumnType::Floatn => {
-                        for val in ba.iter() {
-                            token_rows[rowindex].push(ColumnData::F64(val.map(|x| {
-                                Decimal::from_i128_with_scale(x, scale.into())
-                                    .to_f64()
-                                    .unwrap()
-                            })));
-                            rowindex += 1;
-                        }
+                        ColumnEncoder::Numeric(ba, scale)
                     }
+                    ColumnType::Floatn => ColumnEncoder::DecimalAsF64(ba, scale),
                     _ => {
                         return Err(LakeApi2SqlError::NotSupported {
                             dtype: col.data_type().clone(),
@@ -489,7 +357,375 @@ pub(crate) fn get_token_rows<'a, 'b>(
                     column_type: *coltype,
                 })
             } //other => panic!("Not supported {:?}", other),
+        })
+    }
+
+    /// Strings and binaries are borrowed from the arrow buffers
+    fn borrows(&self) -> bool {
+        matches!(
+            self,
+            ColumnEncoder::Utf8(_)
+                | ColumnEncoder::LargeUtf8(_)
+                | ColumnEncoder::Binary(_)
+                | ColumnEncoder::LargeBinary(_)
+                | ColumnEncoder::FixedSizeBinary(_)
+        )
+    }
+
+    fn value(&self, i: usize, offsets: &DateOffsets) -> ColumnData<'a> {
+        match *self {
+            ColumnEncoder::Missing | ColumnEncoder::NullString => ColumnData::String(None),
+            ColumnEncoder::NullDateTime => ColumnData::DateTime(None),
+            ColumnEncoder::NullBit => ColumnData::Bit(None),
+            ColumnEncoder::NullI32 => ColumnData::I32(None),
+            ColumnEncoder::Bit(ba) => ColumnData::Bit(ba.is_valid(i).then(|| ba.value(i))),
+            ColumnEncoder::I32(ba) => ColumnData::I32(get(ba, i)),
+            ColumnEncoder::Utf8(ba) => {
+                ColumnData::String(ba.is_valid(i).then(|| Cow::from(ba.value(i))))
+            }
+            ColumnEncoder::LargeUtf8(ba) => {
+                ColumnData::String(ba.is_valid(i).then(|| Cow::from(ba.value(i))))
+            }
+            ColumnEncoder::TimestampMs(ba) => {
+                match get(ba, i).and_then(|vs| offsets.timestamp_ms(vs)) {
+                    Some(dt) => to_col_dt(dt.to_sql()),
+                    None => ColumnData::DateTime2(None),
+                }
+            }
+            ColumnEncoder::TimestampUs(ba) => {
+                match get(ba, i).and_then(|vs| offsets.timestamp_us(vs)) {
+                    Some(dt) => to_col_dt(dt.to_sql()),
+                    None => ColumnData::DateTime2(None),
+                }
+            }
+            ColumnEncoder::TimestampUsAsDateTime(ba) => {
+                ColumnData::DateTime(get(ba, i).map(|vs| offsets.sql_datetime(vs)))
+            }
+            ColumnEncoder::TimestampUsAsSmallDateTime(ba) => {
+                ColumnData::SmallDateTime(get(ba, i).map(|vs| offsets.sql_smalldatetime(vs)))
+            }
+            // timezone aware timestamps are in UTC
+            ColumnEncoder::TimestampUsAsDateTimeOffset(ba) => {
+                match get(ba, i).and_then(|vs| offsets.timestamp_us(vs)) {
+                    Some(dt) => to_col_dt(dt.assume_utc().to_sql()),
+                    None => ColumnData::DateTimeOffset(None),
+                }
+            }
+            ColumnEncoder::TimestampNs(ba) => {
+                match get(ba, i).and_then(|vs| offsets.timestamp_ns(vs)) {
+                    Some(dt) => to_col_dt(dt.to_sql()),
+                    None => ColumnData::DateTime2(None),
+                }
+            }
+            ColumnEncoder::U8(ba) => ColumnData::U8(get(ba, i)),
+            ColumnEncoder::I8AsI16(ba) => ColumnData::I16(get(ba, i).map(|v| v as i16)),
+            ColumnEncoder::I8AsU8(ba) => ColumnData::U8(get(ba, i).map(|v| v as u8)),
+            ColumnEncoder::I16(ba) => ColumnData::I16(get(ba, i)),
+            ColumnEncoder::I64(ba) => ColumnData::I64(get(ba, i)),
+            ColumnEncoder::U16(ba) => ColumnData::I32(get(ba, i).map(|x| x.into())),
+            ColumnEncoder::U32(ba) => ColumnData::I64(get(ba, i).map(|x| x.into())),
+            ColumnEncoder::U64(ba) => ColumnData::I64(get(ba, i).map(|x| x as i64)),
+            ColumnEncoder::F16(ba) => ColumnData::F32(get(ba, i).map(|x| x.to_f32())),
+            ColumnEncoder::F32(ba) => ColumnData::F32(get(ba, i)),
+            ColumnEncoder::F64AsI32(ba) => ColumnData::I32(get(ba, i).map(|v| v as i32)),
+            ColumnEncoder::F64(ba) => ColumnData::F64(get(ba, i)),
+            ColumnEncoder::Date32AsDateTime(ba) => match get(ba, i) {
+                Some(vs) => {
+                    let days = offsets.sql_min_dt_to_unix_min + (vs as i64);
+                    ColumnData::DateTime(Some(tiberius::time::DateTime::new(
+                        days.try_into().unwrap(),
+                        0,
+                    )))
+                }
+                None => ColumnData::DateTime(None),
+            },
+            ColumnEncoder::Date32(ba) => match get(ba, i) {
+                Some(vs) => {
+                    let days = offsets.sql_min_to_unix_min + (vs as i64);
+                    ColumnData::Date(Some(tiberius::time::Date::new(days.try_into().unwrap())))
+                }
+                None => ColumnData::Date(None),
+            },
+            ColumnEncoder::Date64(ba) => match get(ba, i) {
+                Some(vs) => {
+                    let days = offsets.sql_min_to_unix_min + vs;
+                    ColumnData::Date(Some(tiberius::time::Date::new(days.try_into().unwrap())))
+                }
+                None => ColumnData::Date(None),
+            },
+            ColumnEncoder::Time32Second(ba) => match get(ba, i) {
+                Some(vs) if vs >= 0 => to_col_dt(
+                    Time::from_hms(
+                        // TODO: Testing
+                        (vs / 60 / 60).try_into().unwrap(),
+                        ((vs / 60) % 60).try_into().unwrap(),
+                        (vs % 60).try_into().unwrap(),
+                    )
+                    .unwrap()
+                    .to_sql(),
+                ),
+                _ => ColumnData::Time(None),
+            },
+            ColumnEncoder::Time32Millisecond(ba) => match get(ba, i) {
+                Some(vs) if vs >= 0 => to_col_dt(
+                    Time::from_hms_milli(
+                        (vs / 1000 / 60 / 60).try_into().unwrap(),
+                        ((vs / 1000 / 60) % 60).try_into().unwrap(),
+                        ((vs / 1000) % 60).try_into().unwrap(),
+                        (vs % 1000).try_into().unwrap(),
+                    )
+                    .unwrap()
+                    .to_sql(),
+                ),
+                _ => ColumnData::Time(None),
+            },
+            ColumnEncoder::Time64Microsecond(ba) => match get(ba, i) {
+                Some(vs) if vs >= 0 => to_col_dt(
+                    Time::from_hms_micro(
+                        (vs / 1_000_000 / 60 / 60).try_into().unwrap(),
+                        ((vs / 1_000_000 / 60) % 60).try_into().unwrap(),
+                        ((vs / 1_000_000) % 60).try_into().unwrap(),
+                        (vs % 1_000_000).try_into().unwrap(),
+                    )
+                    .unwrap()
+                    .to_sql(),
+                ),
+                _ => ColumnData::Time(None),
+            },
+            ColumnEncoder::Binary(ba) => {
+                ColumnData::Binary(ba.is_valid(i).then(|| Cow::from(ba.value(i))))
+            }
+            ColumnEncoder::LargeBinary(ba) => {
+                ColumnData::Binary(ba.is_valid(i).then(|| Cow::from(ba.value(i))))
+            }
+            ColumnEncoder::FixedSizeBinary(ba) => {
+                ColumnData::Binary(ba.is_valid(i).then(|| Cow::from(ba.value(i))))
+            }
+            ColumnEncoder::FixedSizeBinaryAsGuid(ba) => ColumnData::Guid(
+                ba.is_valid(i)
+                    .then(|| Uuid::from_slice(ba.value(i)).expect("16 bytes")),
+            ),
+            ColumnEncoder::Numeric(ba, scale) => {
+                ColumnData::Numeric(get(ba, i).map(|x| Numeric::new_with_scale(x, scale)))
+            }
+            ColumnEncoder::DecimalAsF64(ba, scale) => ColumnData::F64(get(ba, i).map(|x| {
+                Decimal::from_i128_with_scale(x, scale.into())
+                    .to_f64()
+                    .unwrap()
+            })),
+        }
+    }
+}
+
+/// Encodes the rows of a batch one by one, reading the values from the arrow buffers. Each row is
+/// still a `TokenRow`, which `BulkLoadRequest::send` encodes into the packet buffer. Other than
+/// get_token_rows this never holds more than one converted row in memory, writing the row tokens
+/// without a `TokenRow` would need an encoding api in tiberius
+pub struct RowEncoder<'a> {
+    columns: Vec<ColumnEncoder<'a>>,
+    /// Values converted ahead by get_owned_columns, empty if all columns are read from the batch
+    converted: Vec<Option<std::vec::IntoIter<ColumnData<'static>>>>,
+    offsets: DateOffsets,
+    rows: usize,
+    next: usize,
+}
+
+impl<'a> RowEncoder<'a> {
+    pub fn new(
+        batch: &'a RecordBatch,
+        colsnames: &[(String, ColumnType)],
+    ) -> Result<Self, LakeApi2SqlError> {
+        Ok(Self {
+            columns: colsnames
+                .iter()
+                .map(|(colname, coltype)| ColumnEncoder::new(batch, colname, coltype))
+                .collect::<Result<Vec<_>, LakeApi2SqlError>>()?,
+            converted: vec![],
+            offsets: DateOffsets::new(),
+            rows: batch.num_rows(),
+            next: 0,
+        })
+    }
+
+    /// Takes the values of the columns converted by get_owned_columns, the other columns are read from the batch
+    pub fn with_converted(
+        batch: &'a RecordBatch,
+        colsnames: &[(String, ColumnType)],
+        converted: ConvertedColumns,
+    ) -> Result<Self, LakeApi2SqlError> {
+        let mut res = Self::new(batch, colsnames)?;
+        res.converted = converted
+            .into_iter()
+            .map(|c| c.map(|c| c.into_iter()))
+            .collect();
+        Ok(res)
+    }
+}
+
+impl<'a> Iterator for RowEncoder<'a> {
+    type Item = TokenRow<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.rows {
+            return None;
+        }
+        let mut row = TokenRow::with_capacity(self.columns.len());
+        for (j, c) in self.columns.iter().enumerate() {
+            let value = match self.converted.get_mut(j).and_then(Option::as_mut) {
+                Some(values) => values.next().expect("one converted value per row"),
+                None => c.value(self.next, &self.offsets),
+            };
+            row.push(value);
         }
+        self.next += 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.rows - self.next;
+        (remaining, Some(remaining))
     }
-    Ok(token_rows)
+}
+
+fn get_column_data<'a>(
+    batch: &'a RecordBatch,
+    colname: &str,
+    coltype: &ColumnType,
+) -> Result<Vec<ColumnData<'a>>, LakeApi2SqlError> {
+    let encoder = ColumnEncoder::new(batch, colname, coltype)?;
+    let offsets = DateOffsets::new();
+    Ok((0..batch.num_rows())
+        .map(|i| encoder.value(i, &offsets))
+        .collect())
+}
+
+/// Converts each column with `f`, using up to `threads` threads for wide tables
+fn map_columns<T: Send>(
+    colsnames: &[(String, ColumnType)],
+    threads: usize,
+    f: impl Fn(&str, &ColumnType) -> Result<T, LakeApi2SqlError> + Sync,
+) -> Result<Vec<T>, LakeApi2SqlError> {
+    if threads <= 1 || colsnames.len() <= 1 {
+        return colsnames
+            .iter()
+            .map(|(colname, coltype)| f(colname, coltype))
+            .collect();
+    }
+    let chunk_size = (colsnames.len() + threads - 1) / threads;
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles = colsnames
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(colname, coltype)| f(colname, coltype))
+                        .collect::<Result<Vec<_>, LakeApi2SqlError>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut columns = Vec::with_capacity(colsnames.len());
+        for h in handles {
+            columns.extend(h.join().expect("conversion thread panicked")?);
+        }
+        Ok(columns)
+    })
+}
+
+fn get_columns<'a>(
+    batch: &'a RecordBatch,
+    colsnames: &[(String, ColumnType)],
+    threads: usize,
+) -> Result<Vec<Vec<ColumnData<'a>>>, LakeApi2SqlError> {
+    map_columns(colsnames, threads, |colname, coltype| {
+        get_column_data(batch, colname, coltype)
+    })
+}
+
+fn into_token_rows<'a>(columns: Vec<Vec<ColumnData<'a>>>, rows: usize) -> Vec<TokenRow<'a>> {
+    let nr_cols = columns.len();
+    let mut iters = columns
+        .into_iter()
+        .map(|c| c.into_iter())
+        .collect::<Vec<_>>();
+    let mut token_rows: Vec<TokenRow<'a>> = Vec::with_capacity(rows);
+    for _ in 0..rows {
+        let mut row = TokenRow::with_capacity(nr_cols);
+        for it in iters.iter_mut() {
+            row.push(it.next().unwrap());
+        }
+        token_rows.push(row);
+    }
+    token_rows
+}
+
+pub fn get_token_rows<'a, 'b>(
+    batch: &'a RecordBatch,
+    colsnames: &'b Vec<(String, ColumnType)>,
+    threads: usize,
+) -> Result<Vec<TokenRow<'a>>, LakeApi2SqlError> {
+    let columns = get_columns(batch, colsnames, threads)?;
+    Ok(into_token_rows(columns, batch.num_rows()))
+}
+
+fn into_owned(d: ColumnData<'_>) -> ColumnData<'static> {
+    match d {
+        ColumnData::U8(v) => ColumnData::U8(v),
+        ColumnData::I16(v) => ColumnData::I16(v),
+        ColumnData::I32(v) => ColumnData::I32(v),
+        ColumnData::I64(v) => ColumnData::I64(v),
+        ColumnData::F32(v) => ColumnData::F32(v),
+        ColumnData::F64(v) => ColumnData::F64(v),
+        ColumnData::Bit(v) => ColumnData::Bit(v),
+        ColumnData::String(v) => ColumnData::String(v.map(|x| Cow::Owned(x.into_owned()))),
+        ColumnData::Guid(v) => ColumnData::Guid(v),
+        ColumnData::Binary(v) => ColumnData::Binary(v.map(|x| Cow::Owned(x.into_owned()))),
+        ColumnData::Numeric(v) => ColumnData::Numeric(v),
+        ColumnData::Xml(v) => ColumnData::Xml(v.map(|x| Cow::Owned(x.into_owned()))),
+        ColumnData::DateTime(v) => ColumnData::DateTime(v),
+        ColumnData::SmallDateTime(v) => ColumnData::SmallDateTime(v),
+        ColumnData::Time(v) => ColumnData::Time(v),
+        ColumnData::Date(v) => ColumnData::Date(v),
+        ColumnData::DateTime2(v) => ColumnData::DateTime2(v),
+        ColumnData::DateTimeOffset(v) => ColumnData::DateTimeOffset(v),
+    }
+}
+
+/// Same as get_token_rows, but the rows don't borrow from the batch, so they can be sent to another thread.
+/// This copies strings and binaries
+pub fn get_owned_token_rows(
+    batch: &RecordBatch,
+    colsnames: &[(String, ColumnType)],
+    threads: usize,
+) -> Result<Vec<TokenRow<'static>>, LakeApi2SqlError> {
+    let columns = get_columns(batch, colsnames, threads)?
+        .into_iter()
+        .map(|c| c.into_iter().map(into_owned).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    Ok(into_token_rows(columns, batch.num_rows()))
+}
+
+/// Values of the columns of a batch converted by get_owned_columns, None for strings and binaries
+pub type ConvertedColumns = Vec<Option<Vec<ColumnData<'static>>>>;
+
+/// Converts the columns that don't borrow from the batch, so this can run on another thread while the previous
+/// batch is sent. Strings and binaries are not copied, [RowEncoder::with_converted] reads them from the batch
+pub fn get_owned_columns(
+    batch: &RecordBatch,
+    colsnames: &[(String, ColumnType)],
+    threads: usize,
+) -> Result<ConvertedColumns, LakeApi2SqlError> {
+    let offsets = DateOffsets::new();
+    map_columns(colsnames, threads, |colname, coltype| {
+        let encoder = ColumnEncoder::new(batch, colname, coltype)?;
+        if encoder.borrows() {
+            return Ok(None);
+        }
+        Ok(Some(
+            (0..batch.num_rows())
+                .map(|i| into_owned(encoder.value(i, &offsets)))
+                .collect(),
+        ))
+    })
 }
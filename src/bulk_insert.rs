@@ -1,23 +1,147 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use arrow::ffi_stream::ArrowArrayStreamReader;
+use arrow::error::ArrowError;
 use arrow::record_batch::RecordBatchReader;
-use arrow::{datatypes::Schema, ipc::reader::StreamReader, record_batch::RecordBatch};
-use futures::stream::TryStreamExt;
+use arrow::{datatypes::Schema, record_batch::RecordBatch};
+use futures::stream::{StreamExt, TryStreamExt};
 use log::info;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use tiberius::Client;
 use tiberius::ColumnType;
 use tiberius::SqlBulkCopyOptions;
+use tiberius::ToSql;
 use tokio::net::TcpStream;
 use tokio_util::compat::Compat;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tokio_util::io::SyncIoBridge;
+use tokio_util::sync::CancellationToken;
 
 use tokio::sync::mpsc;
 use tokio::task;
 
-use crate::arrow_convert::get_token_rows;
+use crate::arrow_convert::{get_owned_columns, get_token_rows, ConvertedColumns, RowEncoder};
+use crate::checkpoint::{Checkpoint, CheckpointTracker};
+use crate::connect::{connect_sql_many, ConnectInfo};
+use crate::delta::{read_snapshot, DeltaReader, DeltaVersion};
 use crate::error::LakeApi2SqlError;
+use crate::export::DEFAULT_BATCH_ROWS;
+use crate::file_source::{FileSource, FilesReader};
+use crate::http::{check_status, HttpRequest, TokenCache};
+use crate::load_result::{LoadResult, LoadStats};
+use crate::progress::{ProgressCallback, ProgressTracker};
+use crate::rebatch::Rebatcher;
+use crate::retry::RetryPolicy;
+use crate::source_format::{open_reader, DataFormat};
+use crate::sql_to_arrow::query_batches;
+use crate::timeout::{with_idle_timeout, with_timeout};
+use crate::watermark::{filter_batch, get_watermark, Watermark, WatermarkResult};
+
+/// Options for the bulk insert, see also https://learn.microsoft.com/en-us/dotnet/api/system.data.sqlclient.sqlbulkcopyoptions
+#[derive(Debug, Clone)]
+pub struct BulkInsertOptions {
+    pub check_constraints: bool,
+    pub fire_triggers: bool,
+    pub keep_nulls: bool,
+    pub keep_identity: bool,
+    /// Takes a bulk update lock, don't use this with concurrent loads into the same table
+    pub table_lock: bool,
+    /// Sort order of the data for clustered index targets, eg `["id ASC"]`
+    pub order_hints: Vec<String>,
+    /// Slices or concatenates incoming batches to this size before sending them, eg 102400 for columnstore targets
+    pub batch_rows: Option<usize>,
+    /// Number of connections the batches are sent over round robin, see [`BulkInsertOptions::connect`]
+    pub parallelism: usize,
+    /// Number of batches converted ahead on a blocking thread while the current batch is sent.
+    /// 0 converts each row right before sending it
+    pub pipeline_depth: usize,
+    /// Number of threads used to convert the columns of a batch, useful for wide tables
+    pub conversion_threads: usize,
+    /// Called after each committed batch
+    pub progress: Option<Arc<dyn ProgressCallback>>,
+    /// Time limit for sending and committing one batch
+    pub command_timeout: Option<Duration>,
+    /// Time limit for waiting on the next chunk of the http response
+    pub http_read_timeout: Option<Duration>,
+    /// Time limit for the whole load. Batches committed before the timeout stay in the table
+    pub load_timeout: Option<Duration>,
+    /// Retries batches that failed with a transient error
+    pub retry: RetryPolicy,
+    /// Used to replace the connection before a batch is retried. Without it only server errors are retried
+    /// on the same connection, connection resets fail the load
+    pub reconnect: Option<ConnectInfo>,
+    /// Persists the committed rows of an http load, so it can be resumed after a failure.
+    /// Can't be combined with a watermark
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl Default for BulkInsertOptions {
+    fn default() -> Self {
+        Self {
+            check_constraints: false,
+            fire_triggers: false,
+            keep_nulls: false,
+            keep_identity: false,
+            table_lock: true,
+            order_hints: vec![],
+            batch_rows: None,
+            parallelism: 1,
+            pipeline_depth: 1,
+            conversion_threads: 1,
+            progress: None,
+            command_timeout: None,
+            http_read_timeout: None,
+            load_timeout: None,
+            retry: RetryPolicy::default(),
+            reconnect: None,
+            checkpoint: None,
+        }
+    }
+}
+
+impl BulkInsertOptions {
+    /// Checks the values that can't be expressed by their type
+    pub fn validate(&self) -> Result<(), LakeApi2SqlError> {
+        if self.parallelism == 0 {
+            return Err(LakeApi2SqlError::InvalidOptions(
+                "parallelism must be at least 1".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Opens the `parallelism` connections for a load, retrying transient errors
+    pub async fn connect(
+        &self,
+        con_str: &str,
+        aad_token: Option<String>,
+    ) -> Result<Vec<Client<Compat<TcpStream>>>, LakeApi2SqlError> {
+        self.validate()?;
+        connect_sql_many(con_str, aad_token, self.parallelism, &self.retry).await
+    }
+
+    pub fn sql_bulk_copy_options(&self) -> SqlBulkCopyOptions {
+        let mut opts = SqlBulkCopyOptions::empty();
+        if self.check_constraints {
+            opts |= SqlBulkCopyOptions::CheckConstraints;
+        }
+        if self.fire_triggers {
+            opts |= SqlBulkCopyOptions::FireTriggers;
+        }
+        if self.keep_nulls {
+            opts |= SqlBulkCopyOptions::KeepNulls;
+        }
+        if self.keep_identity {
+            opts |= SqlBulkCopyOptions::KeepIdentity;
+        }
+        if self.table_lock {
+            opts |= SqlBulkCopyOptions::TableLock;
+        }
+        opts
+    }
+}
 
 async fn get_cols_from_table(
     db_client: &mut Client<Compat<TcpStream>>,
@@ -43,117 +167,810 @@ async fn get_cols_from_table(
         .collect::<Vec<(String, ColumnType)>>())
 }
 
+/// Sends the rows of the batch, returns the time spent on conversion and on sending
 pub async fn bulk_insert_batch<'a>(
     table_name: &str,
     blk: &mut tiberius::BulkLoadRequest<'a, Compat<TcpStream>>,
     batch: &'a RecordBatch,
     collist: &'a Vec<(String, ColumnType)>,
-) -> Result<(), LakeApi2SqlError> {
+    conversion_threads: usize,
+) -> Result<(Duration, Duration), LakeApi2SqlError> {
     let nrows = batch.num_rows();
     info!("{table_name}: received {nrows}");
-    let rows = task::block_in_place(|| get_token_rows(batch, collist))?;
-    info!("{table_name}: converted {nrows}");
-    for rowdt in rows {
+    let mut conversion_time = Duration::ZERO;
+    let mut network_time = Duration::ZERO;
+    if conversion_threads > 1 {
+        let start = Instant::now();
+        let rows = task::block_in_place(|| get_token_rows(batch, collist, conversion_threads))?;
+        conversion_time += start.elapsed();
+        info!("{table_name}: converted {nrows}");
+        let start = Instant::now();
+        for rowdt in rows {
+            blk.send(rowdt).await?;
+        }
+        network_time += start.elapsed();
+    } else {
+        // the rows are converted while they are sent, so the conversion counts as network time
+        let start = Instant::now();
+        for rowdt in RowEncoder::new(batch, collist)? {
+            blk.send(rowdt).await?;
+        }
+        network_time += start.elapsed();
+    }
+    info!("{table_name}: Written {nrows}");
+    Ok((conversion_time, network_time))
+}
+
+async fn insert_batch(
+    db_client: &mut Client<Compat<TcpStream>>,
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    collist: &Vec<(String, ColumnType)>,
+    batch: &RecordBatch,
+) -> Result<LoadStats, LakeApi2SqlError> {
+    let order_hints = options
+        .order_hints
+        .iter()
+        .map(|x| x.as_str())
+        .collect::<Vec<&str>>();
+    let mut blk = db_client
+        .bulk_insert_with_options(
+            table_name,
+            column_names,
+            options.sql_bulk_copy_options(),
+            &order_hints,
+        )
+        .await?;
+    let (conversion_time, network_time) = bulk_insert_batch(
+        table_name,
+        &mut blk,
+        batch,
+        collist,
+        options.conversion_threads,
+    )
+    .await?;
+    let start = Instant::now();
+    let rows_written = blk.finalize().await?.total();
+    Ok(LoadStats {
+        rows_written,
+        batches: 1,
+        conversion_time,
+        network_time,
+        server_time: start.elapsed(),
+        ..Default::default()
+    })
+}
+
+/// Same as insert_batch, with the columns converted ahead by get_owned_columns
+#[allow(clippy::too_many_arguments)]
+async fn insert_converted(
+    db_client: &mut Client<Compat<TcpStream>>,
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    collist: &[(String, ColumnType)],
+    batch: &RecordBatch,
+    columns: ConvertedColumns,
+) -> Result<LoadStats, LakeApi2SqlError> {
+    let order_hints = options
+        .order_hints
+        .iter()
+        .map(|x| x.as_str())
+        .collect::<Vec<&str>>();
+    let mut blk = db_client
+        .bulk_insert_with_options(
+            table_name,
+            column_names,
+            options.sql_bulk_copy_options(),
+            &order_hints,
+        )
+        .await?;
+    let nrows = batch.num_rows();
+    let start = Instant::now();
+    for rowdt in RowEncoder::with_converted(batch, collist, columns)? {
         blk.send(rowdt).await?;
     }
+    let network_time = start.elapsed();
     info!("{table_name}: Written {nrows}");
-    Ok(())
+    let start = Instant::now();
+    let rows_written = blk.finalize().await?.total();
+    Ok(LoadStats {
+        rows_written,
+        batches: 1,
+        network_time,
+        server_time: start.elapsed(),
+        ..Default::default()
+    })
+}
+
+/// Retries a failed batch. The failed bulk load was not committed, so the whole batch is sent again,
+/// batches committed before are not touched. Without `reconnect` only server errors are retried, after
+/// an io error the connection is in an unknown state
+async fn retry_batch(
+    db_client: &mut Client<Compat<TcpStream>>,
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    collist: &Vec<(String, ColumnType)>,
+    batch: &RecordBatch,
+    err: LakeApi2SqlError,
+) -> Result<LoadStats, LakeApi2SqlError> {
+    let mut err = err;
+    let mut attempt = 0;
+    loop {
+        let can_resend = options.reconnect.is_some()
+            || matches!(
+                err,
+                LakeApi2SqlError::TiberiusError(tiberius::error::Error::Server(_))
+            );
+        if !can_resend || !options.retry.should_retry(&err, attempt) {
+            return Err(err);
+        }
+        let wait = options.retry.backoff(attempt);
+        log::warn!(
+            "{table_name}: transient error {err}, retrying batch in {:?}",
+            wait
+        );
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+        if let Some(ci) = &options.reconnect {
+            // the failed bulk load leaves the connection in an unknown state
+            match ci.connect(&options.retry).await {
+                Ok(c) => *db_client = c,
+                Err(e) => {
+                    err = e;
+                    continue;
+                }
+            }
+        }
+        match with_timeout(
+            options.command_timeout,
+            "batch insert",
+            insert_batch(db_client, table_name, column_names, options, collist, batch),
+        )
+        .await
+        {
+            Ok(s) => return Ok(s),
+            Err(e) => err = e,
+        }
+    }
+}
+
+/// Position of the batches of one connection in the stream. With round robin dispatch connection `i` of
+/// `n` gets the batches `i`, `i + n`, `i + 2n`, ...
+struct BatchSeq {
+    next: u64,
+    step: u64,
+}
+
+impl BatchSeq {
+    fn next(&mut self) -> u64 {
+        let res = self.next;
+        self.next += self.step;
+        res
+    }
+}
+
+/// Writes all batches over one connection. With a pipeline_depth the following batches are converted on
+/// a blocking thread while the current one is sent
+#[allow(clippy::too_many_arguments)]
+async fn write_connection(
+    db_client: &mut Client<Compat<TcpStream>>,
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    collist: &Vec<(String, ColumnType)>,
+    progress: &ProgressTracker,
+    mut seq: BatchSeq,
+    mut batches: mpsc::Receiver<RecordBatch>,
+) -> Result<LoadStats, LakeApi2SqlError> {
+    let mut stats = LoadStats::default();
+    if options.pipeline_depth == 0 {
+        while let Some(b) = batches.recv().await {
+            let s = match with_timeout(
+                options.command_timeout,
+                "batch insert",
+                insert_batch(db_client, table_name, column_names, options, collist, &b),
+            )
+            .await
+            {
+                Ok(s) => s,
+                Err(e) => {
+                    retry_batch(db_client, table_name, column_names, options, collist, &b, e)
+                        .await?
+                }
+            };
+            progress.batch_committed(seq.next(), s.rows_written)?;
+            stats.add(&s);
+        }
+        return Ok(stats);
+    }
+    // the batch is kept until it is committed, in case it has to be retried
+    let (tx, mut rx) = mpsc::channel::<(RecordBatch, ConvertedColumns)>(options.pipeline_depth);
+    let shared_collist = Arc::new(collist.clone());
+    let conversion_threads = options.conversion_threads;
+    let convert = async move {
+        let mut conversion_time = Duration::ZERO;
+        while let Some(b) = batches.recv().await {
+            let collist = shared_collist.clone();
+            let nrows = b.num_rows();
+            info!("{table_name}: received {nrows}");
+            let start = Instant::now();
+            let to_convert = b.clone();
+            let columns = task::spawn_blocking(move || {
+                get_owned_columns(&to_convert, &collist, conversion_threads)
+            })
+            .await??;
+            conversion_time += start.elapsed();
+            info!("{table_name}: converted {nrows}");
+            if tx.send((b, columns)).await.is_err() {
+                // sending failed, the error is returned by the writer
+                break;
+            }
+        }
+        Ok::<Duration, LakeApi2SqlError>(conversion_time)
+    };
+    let write = async {
+        while let Some((b, columns)) = rx.recv().await {
+            let s = match with_timeout(
+                options.command_timeout,
+                "batch insert",
+                insert_converted(
+                    db_client,
+                    table_name,
+                    column_names,
+                    options,
+                    collist,
+                    &b,
+                    columns,
+                ),
+            )
+            .await
+            {
+                Ok(s) => s,
+                Err(e) => {
+                    retry_batch(db_client, table_name, column_names, options, collist, &b, e)
+                        .await?
+                }
+            };
+            progress.batch_committed(seq.next(), s.rows_written)?;
+            stats.add(&s);
+        }
+        Ok::<(), LakeApi2SqlError>(())
+    };
+    let (conversion_time, _) = futures::try_join!(convert, write)?;
+    stats.conversion_time += conversion_time;
+    Ok(stats)
+}
+
+/// Writes the batches round robin over all connections.
+/// The first error stops all connections
+async fn write_batches(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    collist: &Vec<(String, ColumnType)>,
+    progress: &ProgressTracker,
+    mut batches: mpsc::Receiver<RecordBatch>,
+) -> Result<LoadStats, LakeApi2SqlError> {
+    if db_clients.len() == 1 {
+        return write_connection(
+            &mut db_clients[0],
+            table_name,
+            column_names,
+            options,
+            collist,
+            progress,
+            BatchSeq { next: 0, step: 1 },
+            batches,
+        )
+        .await;
+    }
+    if options.table_lock {
+        log::warn!(
+            "{table_name}: parallel load with table_lock, this only works for heaps without indexes"
+        );
+    }
+    let nr_clients = db_clients.len() as u64;
+    let mut senders = Vec::with_capacity(db_clients.len());
+    let mut workers = Vec::with_capacity(db_clients.len());
+    for (i, db_client) in db_clients.iter_mut().enumerate() {
+        let (tx, rx) = mpsc::channel::<RecordBatch>(1);
+        senders.push(tx);
+        workers.push(write_connection(
+            db_client,
+            table_name,
+            column_names,
+            options,
+            collist,
+            progress,
+            BatchSeq {
+                next: i as u64,
+                step: nr_clients,
+            },
+            rx,
+        ));
+    }
+    let dispatch = async move {
+        let mut next = 0;
+        while let Some(b) = batches.recv().await {
+            if senders[next].send(b).await.is_err() {
+                // the worker failed, its error is returned by try_join_all
+                break;
+            }
+            next = (next + 1) % senders.len();
+        }
+        Ok::<(), LakeApi2SqlError>(())
+    };
+    let (_, worker_stats) = futures::try_join!(dispatch, futures::future::try_join_all(workers))?;
+    let mut stats = LoadStats::default();
+    for s in worker_stats.iter() {
+        stats.add(s);
+    }
+    Ok(stats)
+}
+
+/// Skips, filters and rebatches the incoming batches and writes them to the table.
+/// Returns the columns of the target table
+#[allow(clippy::too_many_arguments)]
+async fn write_stream(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    progress: &ProgressTracker,
+    mut source: mpsc::Receiver<RecordBatch>,
+    skip_rows: u64,
+    watermark_filter: Option<(&str, &str)>,
+) -> Result<(Vec<(String, ColumnType)>, LoadStats), LakeApi2SqlError> {
+    let collist = get_cols_from_table(&mut db_clients[0], table_name, column_names).await?;
+    log::debug!("{:?}", collist);
+    let (tx, rx) = mpsc::channel::<RecordBatch>(db_clients.len());
+    let prepare = async move {
+        let mut rows_read = 0;
+        let mut to_skip = skip_rows;
+        let mut rebatcher = Rebatcher::new(options.batch_rows);
+        while let Some(v) = source.recv().await {
+            rows_read += v.num_rows() as u64;
+            let v = if to_skip > 0 {
+                let skipped = to_skip.min(v.num_rows() as u64) as usize;
+                to_skip -= skipped as u64;
+                v.slice(skipped, v.num_rows() - skipped)
+            } else {
+                v
+            };
+            let v = match watermark_filter {
+                Some((column, wm)) => filter_batch(v, column, wm)?,
+                None => v,
+            };
+            if v.num_rows() == 0 {
+                continue;
+            }
+            for b in rebatcher.push(v)? {
+                tx.send(b).await?;
+            }
+        }
+        if let Some(b) = rebatcher.finish()? {
+            tx.send(b).await?;
+        }
+        Ok::<u64, LakeApi2SqlError>(rows_read)
+    };
+    let nr_clients = db_clients.len();
+    let (rows_read, mut stats) = futures::try_join!(
+        prepare,
+        write_batches(
+            db_clients,
+            table_name,
+            column_names,
+            options,
+            &collist,
+            progress,
+            rx
+        )
+    )?;
+    stats.rows_read = rows_read;
+    info!(
+        "{table_name}: Written {} rows over {nr_clients} connection(s)",
+        stats.rows_written
+    );
+    Ok((collist, stats))
 }
 
-pub async fn bulk_insert<'a>(
-    db_client: &'a mut Client<Compat<TcpStream>>,
+/// Loads the arrow stream from the url into the table. The batches are distributed round robin over all
+/// given connections, the first connection is also used to read the table metadata
+pub async fn bulk_insert(
+    db_clients: &mut [Client<Compat<TcpStream>>],
     table_name: &str,
-    column_names: &'a [&'a str],
+    column_names: &[&str],
     url: &str,
     user: &str,
     password: &str,
-) -> Result<Arc<Schema>, LakeApi2SqlError> {
-    //let mut row = TokenRow::new();
-    //row.push(1.into_sql());
-    //blk.send(row).await?;
-    //blk.finalize().await?;
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    bulk_insert_request(
+        db_clients,
+        table_name,
+        column_names,
+        &HttpRequest::with_basic_auth(url, user, password),
+        options,
+    )
+    .await
+}
 
-    let collist = get_cols_from_table(db_client, table_name, column_names).await?;
-    log::debug!("{:?}", collist);
-    let cclient = reqwest::Client::new();
+/// Same as `bulk_insert`, for endpoints that need another authentication than basic auth
+pub async fn bulk_insert_request(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    request: &HttpRequest,
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    with_timeout(
+        options.load_timeout,
+        "load",
+        bulk_insert_http(
+            db_clients,
+            table_name,
+            column_names,
+            request,
+            options,
+            None,
+            None,
+        ),
+    )
+    .await
+}
 
-    // a bit too complex if you ask me: https://github.com/benkay86/async-applied/tree/master/reqwest-tokio-compat
+/// Loads only rows newer than the current maximum of the watermark column in the target table.
+/// The current watermark is passed to the http endpoint as query parameter and rows at or below it
+/// are filtered out before inserting
+pub async fn bulk_insert_incremental(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    request: &HttpRequest,
+    options: &BulkInsertOptions,
+    watermark: &Watermark,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    if options.checkpoint.is_some() {
+        return Err(LakeApi2SqlError::InvalidOptions(
+            "a checkpoint can't be combined with a watermark, the watermark already resumes the load"
+                .to_owned(),
+        ));
+    }
+    let old = get_watermark(&mut db_clients[0], table_name, &watermark.column).await?;
+    info!("{table_name}: current watermark {:?}", old);
+    let mut res = with_timeout(
+        options.load_timeout,
+        "load",
+        bulk_insert_http(
+            db_clients,
+            table_name,
+            column_names,
+            request,
+            options,
+            old.as_ref()
+                .map(|o| (watermark.query_param.as_str(), o.as_str())),
+            old.as_ref()
+                .map(|o| (watermark.column.as_str(), o.as_str())),
+        ),
+    )
+    .await?;
+    let new = get_watermark(&mut db_clients[0], table_name, &watermark.column).await?;
+    info!("{table_name}: new watermark {:?}", new);
+    res.watermark = Some(WatermarkResult {
+        column: watermark.column.clone(),
+        old,
+        new,
+    });
+    Ok(res)
+}
 
-    let res = cclient
-        .get(url)
-        .basic_auth(user, Some(password))
-        .send()
-        .await?
-        .error_for_status()?;
-
-    info!("received http response");
-    let res = res
-        .bytes_stream()
-        .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
-        .into_async_read()
-        .compat();
-    let (tx, mut rx) = mpsc::channel::<RecordBatch>(2);
-    let syncstr = SyncIoBridge::new(res);
-    let worker = tokio::task::spawn_blocking(move || -> Result<Arc<Schema>, LakeApi2SqlError> {
-        let mut reader = StreamReader::try_new(syncstr, None)?;
-        let schema = reader.schema();
-        loop {
-            match reader.next() {
-                Some(x) => match x {
-                    Ok(b) => {
-                        tx.blocking_send(b)?;
-                    }
-                    Err(l) => println!("{:?}", l),
-                },
-                None => break,
+async fn bulk_insert_http(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    request: &HttpRequest,
+    options: &BulkInsertOptions,
+    query_param: Option<(&str, &str)>,
+    watermark_filter: Option<(&str, &str)>,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    let cclient = request.client()?;
+    let tokens = TokenCache::default();
+
+    let mut query: Vec<(String, String)> = vec![];
+    if let Some((k, v)) = query_param {
+        query.push((k.to_owned(), v.to_owned()));
+    }
+    let mut progress = ProgressTracker::new(options.progress.clone());
+    let mut skip_rows = 0;
+    let mut rows_skipped = 0;
+    if let Some(cp) = &options.checkpoint {
+        if cp.skip_param.is_some() && request.pagination.is_some() {
+            return Err(LakeApi2SqlError::InvalidOptions(
+                "a checkpoint skip parameter cannot be combined with pagination".to_owned(),
+            ));
+        }
+        rows_skipped = cp.read()?;
+        if rows_skipped > 0 {
+            info!("{table_name}: resuming after {rows_skipped} committed rows");
+            match &cp.skip_param {
+                Some(p) => query.push((p.clone(), rows_skipped.to_string())),
+                None => skip_rows = rows_skipped,
+            }
+        }
+        progress = progress.with_checkpoint(CheckpointTracker::new(cp.clone(), rows_skipped));
+    }
+
+    let (tx, rx) = mpsc::channel::<RecordBatch>(2);
+    let counter = progress.bytes_counter();
+    let read_pages = async move {
+        let mut schema: Option<Arc<Schema>> = None;
+        let mut next = Some(
+            request
+                .pagination
+                .as_ref()
+                .map(|p| p.first_page())
+                .unwrap_or_default(),
+        );
+        let mut pages = 0;
+        while let Some(page) = next.take() {
+            let req = match &page.url {
+                Some(url) => {
+                    let next_request = HttpRequest {
+                        url: url.clone(),
+                        query: vec![],
+                        ..request.clone()
+                    };
+                    next_request.build(&cclient, &tokens).await?
+                }
+                None => request
+                    .build(&cclient, &tokens)
+                    .await?
+                    .query(&query)
+                    .query(&page.query),
             };
+            let res = check_status(req.send().await?)?;
+            let url = res.url().clone();
+            let headers = res.headers().clone();
+            pages += 1;
+            let (page_schema, rows) = read_response(
+                res,
+                request.format,
+                counter.clone(),
+                options.http_read_timeout,
+                schema.clone(),
+                tx.clone(),
+            )
+            .await
+            .map_err(|e| match e {
+                LakeApi2SqlError::SchemaMismatch(m) => {
+                    LakeApi2SqlError::SchemaMismatch(format!("page {pages} {m}"))
+                }
+                e => e,
+            })?;
+            schema.get_or_insert(page_schema);
+            next = request
+                .pagination
+                .as_ref()
+                .and_then(|p| p.next_page(&page, &url, &headers, rows));
         }
-        Ok(schema)
-    });
-    while let Some(v) = rx.recv().await {
-        let mut blk = db_client
-            .bulk_insert_with_options(table_name, column_names, SqlBulkCopyOptions::TableLock, &[])
-            .await?;
-        bulk_insert_batch(table_name, &mut blk, &v, &collist).await?;
-        blk.finalize().await?;
+        info!("{table_name}: read {pages} page(s)");
+        drop(tx);
+        Ok(schema.unwrap_or_else(|| Arc::new(Schema::empty())))
+    };
+    let ((collist, mut stats), schema) = futures::try_join!(
+        write_stream(
+            db_clients,
+            table_name,
+            column_names,
+            options,
+            &progress,
+            rx,
+            skip_rows,
+            watermark_filter,
+        ),
+        read_pages
+    )?;
+
+    if let Some(cp) = &options.checkpoint {
+        cp.remove()?;
+    }
+    stats.bytes_received = progress.bytes_received();
+    stats.rows_skipped = rows_skipped;
+    Ok(LoadResult::new(schema, &collist, stats))
+}
+
+/// Errors of a source reader, io errors like a dropped connection stay io errors
+fn read_error(e: ArrowError) -> LakeApi2SqlError {
+    match e {
+        ArrowError::IoError(_, e) => LakeApi2SqlError::IOError(e),
+        e => e.into(),
     }
+}
 
+/// Reads one http response into `tx`, returns its schema and the number of rows.
+/// A response that doesn't match `expected_schema` fails before any of its batches is sent
+async fn read_response(
+    res: reqwest::Response,
+    format: Option<DataFormat>,
+    counter: Arc<AtomicU64>,
+    read_timeout: Option<Duration>,
+    expected_schema: Option<Arc<Schema>>,
+    tx: mpsc::Sender<RecordBatch>,
+) -> Result<(Arc<Schema>, u64), LakeApi2SqlError> {
+    let format = format
+        .or_else(|| {
+            res.headers()
+                .get(CONTENT_TYPE)
+                .and_then(|ct| ct.to_str().ok())
+                .and_then(DataFormat::from_content_type)
+        })
+        .unwrap_or(DataFormat::ArrowStream);
+    // gzip, brotli and deflate are decoded by reqwest already
+    let zstd_encoded = res
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|e| e.as_bytes().eq_ignore_ascii_case(b"zstd"));
+    info!("received http response, reading {format:?}");
+    // ends the download if the load fails or gets cancelled while the reader thread waits for data
+    let cancel = CancellationToken::new();
+    let _cancel_guard = cancel.clone().drop_guard();
+    let timed_out = Arc::new(AtomicBool::new(false));
+    // a bit too complex if you ask me: https://github.com/benkay86/async-applied/tree/master/reqwest-tokio-compat
+    let res = Box::pin(with_idle_timeout(
+        res.bytes_stream()
+            .take_until(cancel.cancelled_owned())
+            .inspect_ok(move |b| {
+                counter.fetch_add(b.len() as u64, Ordering::Relaxed);
+            })
+            .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e)),
+        read_timeout,
+        timed_out.clone(),
+    ))
+    .into_async_read()
+    .compat();
+    let syncstr = SyncIoBridge::new(res);
+    let worker =
+        tokio::task::spawn_blocking(move || -> Result<(Arc<Schema>, u64), LakeApi2SqlError> {
+            let source: Box<dyn std::io::Read + Send> = if zstd_encoded {
+                Box::new(zstd::Decoder::new(syncstr)?)
+            } else {
+                Box::new(syncstr)
+            };
+            let stalled = || LakeApi2SqlError::Timeout("http response stalled".to_owned());
+            let reader = open_reader(format, source)?;
+            let schema = reader.schema();
+            if let Some(s) = expected_schema.filter(|s| s.fields() != schema.fields()) {
+                return Err(LakeApi2SqlError::SchemaMismatch(format!(
+                    "has schema {schema:?}, expected {s:?}"
+                )));
+            }
+            let mut rows = 0;
+            for b in reader {
+                // a dropped connection or a truncated body fails the load, so a checkpoint is kept
+                let b = match b {
+                    Ok(b) => b,
+                    Err(_) if timed_out.load(Ordering::Relaxed) => return Err(stalled()),
+                    Err(e) => return Err(read_error(e)),
+                };
+                rows += b.num_rows() as u64;
+                tx.blocking_send(b)?;
+            }
+            if timed_out.load(Ordering::Relaxed) {
+                return Err(stalled());
+            }
+            Ok((schema, rows))
+        });
     worker.await?
 }
 
-pub async fn bulk_insert_reader(
-    db_client: &mut Client<Compat<TcpStream>>,
+/// Loads the batches of the reader into the table. The batches are distributed round robin over all
+/// given connections, the first connection is also used to read the table metadata.
+/// The reader runs on a blocking thread, so reading the next batches doesn't hold up the sends
+pub async fn bulk_insert_reader<R: RecordBatchReader + Send + 'static>(
+    db_clients: &mut [Client<Compat<TcpStream>>],
     table_name: &str,
     column_names: &[&str],
-    reader: &mut ArrowArrayStreamReader,
-) -> Result<Arc<Schema>, LakeApi2SqlError> {
-    //let mut row = TokenRow::new();
-    //row.push(1.into_sql());
-    //blk.send(row).await?;
-    //blk.finalize().await?;
-
-    let collist = get_cols_from_table(db_client, table_name, column_names).await?;
-    log::debug!("{:?}", collist);
+    reader: R,
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
     let schema = reader.schema();
-    loop {
-        match reader.next() {
-            Some(x) => match x {
-                Ok(b) => {
-                    let mut blk = db_client
-                        .bulk_insert_with_options(
-                            table_name,
-                            column_names,
-                            SqlBulkCopyOptions::TableLock,
-                            &[],
-                        )
-                        .await?;
-                    bulk_insert_batch(table_name, &mut blk, &b, &collist).await?;
-                    blk.finalize().await?;
-                }
-                Err(l) => println!("{:?}", l),
-            },
-            None => break,
-        };
-    }
-    Ok(schema)
+    let progress = ProgressTracker::new(options.progress.clone());
+    let (tx, rx) = mpsc::channel::<RecordBatch>(2);
+    let worker = task::spawn_blocking(move || {
+        // a broken file fails the load instead of being left out
+        for b in reader {
+            tx.blocking_send(b.map_err(read_error)?)?;
+        }
+        Ok::<(), LakeApi2SqlError>(())
+    });
+    let read = async move { worker.await? };
+    let (_, (collist, stats)) = with_timeout(options.load_timeout, "load", async {
+        futures::try_join!(
+            read,
+            write_stream(
+                db_clients,
+                table_name,
+                column_names,
+                options,
+                &progress,
+                rx,
+                0,
+                None
+            )
+        )
+    })
+    .await?;
+    Ok(LoadResult::new(schema, &collist, stats))
+}
+
+/// Loads the result of a query on another connection into the table. The rows are read in batches
+/// of `batch_rows` (8192 if not set) while the previous batches are written, so only a few
+/// batches are held in memory
+#[allow(clippy::too_many_arguments)]
+pub async fn bulk_insert_query(
+    source: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    let progress = ProgressTracker::new(options.progress.clone());
+    let (tx, rx) = mpsc::channel::<RecordBatch>(2);
+    let batch_rows = options.batch_rows.unwrap_or(DEFAULT_BATCH_ROWS);
+    let ((schema, _), (collist, stats)) = with_timeout(options.load_timeout, "load", async {
+        futures::try_join!(
+            query_batches(source, query, params, batch_rows, tx),
+            write_stream(
+                db_clients,
+                table_name,
+                column_names,
+                options,
+                &progress,
+                rx,
+                0,
+                None
+            )
+        )
+    })
+    .await?;
+    Ok(LoadResult::new(schema, &collist, stats))
+}
+
+/// Loads a local delta table, the files are read one after the other
+pub async fn bulk_insert_delta(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    delta_path: &Path,
+    version: DeltaVersion,
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    let snapshot = read_snapshot(delta_path, version)?;
+    info!(
+        "{table_name}: loading version {} of {} with {} files",
+        snapshot.version,
+        delta_path.display(),
+        snapshot.files.len()
+    );
+    let reader = DeltaReader::new(snapshot);
+    bulk_insert_reader(db_clients, table_name, column_names, reader, options).await
+}
+
+/// Loads local files, see [FileSource]
+pub async fn bulk_insert_files(
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    source: &FileSource,
+    options: &BulkInsertOptions,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    let reader = FilesReader::try_new(source.clone())?;
+    info!("{table_name}: loading {}", source.pattern);
+    bulk_insert_reader(db_clients, table_name, column_names, reader, options).await
 }
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::error::LakeApi2SqlError;
+
+/// Persists the number of committed rows of a load in a local file, so an interrupted load can continue
+/// where it stopped. The file is removed once the load completed
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    /// Query parameter that tells the endpoint how many rows to skip. Without it the rows are downloaded
+    /// again and skipped client side
+    pub skip_param: Option<String>,
+}
+
+impl Checkpoint {
+    /// Rows committed by an earlier attempt, 0 if there is no checkpoint file
+    pub fn read(&self) -> Result<u64, LakeApi2SqlError> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        content
+            .lines()
+            .find_map(|l| l.strip_prefix("rows="))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .ok_or_else(|| {
+                LakeApi2SqlError::InvalidCheckpoint(self.path.to_string_lossy().into_owned())
+            })
+    }
+
+    fn write(&self, rows: u64) -> Result<(), LakeApi2SqlError> {
+        // write and rename, so a crash never leaves a half written file
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, format!("rows={rows}\n"))?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub(crate) fn remove(&self) -> Result<(), LakeApi2SqlError> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct CommittedBatches {
+    next_seq: u64,
+    rows: u64,
+    out_of_order: BTreeMap<u64, u64>,
+}
+
+/// With parallel connections batches commit out of order, only the rows of the batches committed without
+/// a gap are persisted
+pub(crate) struct CheckpointTracker {
+    checkpoint: Checkpoint,
+    committed: Mutex<CommittedBatches>,
+}
+
+impl CheckpointTracker {
+    pub(crate) fn new(checkpoint: Checkpoint, start_rows: u64) -> Self {
+        Self {
+            checkpoint,
+            committed: Mutex::new(CommittedBatches {
+                next_seq: 0,
+                rows: start_rows,
+                out_of_order: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// `seq` is the position of the batch in the stream, starting at 0
+    pub(crate) fn batch_committed(&self, seq: u64, rows: u64) -> Result<(), LakeApi2SqlError> {
+        let mut committed = self.committed.lock().unwrap();
+        committed.out_of_order.insert(seq, rows);
+        let mut advanced = false;
+        loop {
+            let next = committed.next_seq;
+            match committed.out_of_order.remove(&next) {
+                Some(r) => {
+                    committed.rows += r;
+                    committed.next_seq += 1;
+                    advanced = true;
+                }
+                None => break,
+            }
+        }
+        if advanced {
+            self.checkpoint.write(committed.rows)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use tiberius::client::AdoNetConfig;
 use tiberius::client::ConfigString;
 use tiberius::error::Error;
@@ -10,10 +12,38 @@ use tokio_util::compat::Compat;
 use tokio_util::compat::TokioAsyncWriteCompatExt;
 
 use crate::error::LakeApi2SqlError;
+use crate::retry::RetryPolicy;
+use crate::timeout::{command_timeout, connect_timeout, with_timeout};
 
+/// Opens a connection, honoring `Connect Timeout`, `ConnectRetryCount` and `ConnectRetryInterval` of the
+/// connection string
 pub async fn connect_sql(
     con_str: &str,
     aad_token: Option<String>,
+) -> Result<Client<Compat<TcpStream>>, LakeApi2SqlError> {
+    connect_sql_with_retry(con_str, aad_token, &RetryPolicy::from_con_str(con_str)).await
+}
+
+/// Opens a connection, retrying transient errors. The connect timeout applies to each attempt
+pub async fn connect_sql_with_retry(
+    con_str: &str,
+    aad_token: Option<String>,
+    retry: &RetryPolicy,
+) -> Result<Client<Compat<TcpStream>>, LakeApi2SqlError> {
+    retry
+        .run("connecting", || {
+            with_timeout(
+                connect_timeout(con_str),
+                "connecting",
+                open_connection(con_str, aad_token.clone()),
+            )
+        })
+        .await
+}
+
+async fn open_connection(
+    con_str: &str,
+    aad_token: Option<String>,
 ) -> Result<Client<Compat<TcpStream>>, LakeApi2SqlError> {
     let mut config = Config::from_ado_string(con_str)?;
     if let Some(tv) = aad_token.clone() {
@@ -59,3 +89,102 @@ pub async fn connect_sql(
     };
     Ok(client)
 }
+
+/// Opens `count` connections at once, eg for parallel bulk inserts
+pub async fn connect_sql_many(
+    con_str: &str,
+    aad_token: Option<String>,
+    count: usize,
+    retry: &RetryPolicy,
+) -> Result<Vec<Client<Compat<TcpStream>>>, LakeApi2SqlError> {
+    futures::future::try_join_all(
+        (0..count.max(1)).map(|_| connect_sql_with_retry(con_str, aad_token.clone(), retry)),
+    )
+    .await
+}
+
+/// Everything needed to open another connection, eg to replace one that broke during a load
+#[derive(Clone)]
+pub struct ConnectInfo {
+    pub connection_string: String,
+    pub aad_token: Option<String>,
+}
+
+impl std::fmt::Debug for ConnectInfo {
+    // don't print any secrets
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ConnectInfo")
+    }
+}
+
+impl ConnectInfo {
+    pub async fn connect(
+        &self,
+        retry: &RetryPolicy,
+    ) -> Result<Client<Compat<TcpStream>>, LakeApi2SqlError> {
+        connect_sql_with_retry(&self.connection_string, self.aad_token.clone(), retry).await
+    }
+}
+
+/// A connection that can survive cancelled queries. tiberius can't send an attention, so the client is
+/// taken out for the duration of a query and only put back once the query completed. If the query gets
+/// cancelled or times out the client is dropped, which closes the socket and makes the server abort and
+/// roll back the statement. The next query opens a new connection
+pub struct ReconnectingClient {
+    connection_string: String,
+    aad_token: Option<String>,
+    command_timeout: Option<Duration>,
+    client: Option<Client<Compat<TcpStream>>>,
+}
+
+impl ReconnectingClient {
+    pub async fn connect(
+        con_str: &str,
+        aad_token: Option<String>,
+    ) -> Result<Self, LakeApi2SqlError> {
+        let client = connect_sql(con_str, aad_token.clone()).await?;
+        Ok(Self {
+            connection_string: con_str.to_owned(),
+            aad_token,
+            command_timeout: command_timeout(con_str),
+            client: Some(client),
+        })
+    }
+
+    /// Takes the client for a query, reconnects if the last query was cancelled
+    pub async fn take(&mut self) -> Result<Client<Compat<TcpStream>>, LakeApi2SqlError> {
+        match self.client.take() {
+            Some(c) => Ok(c),
+            None => {
+                log::info!("previous query was cancelled or did not complete, reconnecting");
+                connect_sql(&self.connection_string, self.aad_token.clone()).await
+            }
+        }
+    }
+
+    /// `Command Timeout` of the connection string
+    pub fn command_timeout(&self) -> Option<Duration> {
+        self.command_timeout
+    }
+
+    /// Puts the client back after the query completed, successful or not
+    pub fn give_back(&mut self, client: Client<Compat<TcpStream>>) {
+        self.client = Some(client);
+    }
+
+    /// Puts the client back if the query read its whole response. After a timeout or an error on our
+    /// side, eg a failing export writer, the rest of the response may still be on the wire, so the
+    /// client is dropped and the next query reconnects. Errors of the server end the response
+    pub fn give_back_if_done<T>(
+        &mut self,
+        client: Client<Compat<TcpStream>>,
+        res: &Result<T, LakeApi2SqlError>,
+    ) {
+        match res {
+            Ok(_) | Err(LakeApi2SqlError::TiberiusError(Error::Server(_))) => {
+                self.give_back(client)
+            }
+            Err(e) => log::info!("closing the connection, the query did not complete: {e}"),
+        }
+    }
+}
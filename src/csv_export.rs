@@ -0,0 +1,409 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use arrow::array::{Array, ArrayRef};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use encoding_rs::{EncoderResult, Encoding};
+use tokio::sync::mpsc;
+
+use crate::error::LakeApi2SqlError;
+use crate::export::Written;
+use crate::sql_to_arrow::{is_uuid, uuid_strings};
+
+/// Version of the non-XML format files, understood by SQL Server 2008 and later
+const FORMAT_FILE_VERSION: &str = "10.0";
+
+/// When values get quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quoting {
+    /// Only values containing the delimiter, the quote, a line break or looking like the null
+    /// value
+    #[default]
+    Minimal,
+    /// All values but nulls
+    All,
+    /// Never, values containing the delimiter or the row terminator are an error. This is what
+    /// bcp writes and BULK INSERT reads without `FORMAT = 'CSV'`
+    Never,
+}
+
+impl Quoting {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "minimal" => Some(Self::Minimal),
+            "all" => Some(Self::All),
+            "none" | "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Encoding of the written file
+#[derive(Debug, Clone, Copy, Default)]
+pub enum CsvEncoding {
+    #[default]
+    Utf8,
+    /// Little endian with a byte order mark, what bcp writes with `-w`
+    Utf16,
+    CodePage(&'static Encoding),
+}
+
+impl CsvEncoding {
+    /// Parses `utf-8`, `utf-16`, a Windows code page number like `1252` or an encoding label like
+    /// `windows-1252` or `latin1`
+    pub fn from_name(name: &str) -> Result<Self, LakeApi2SqlError> {
+        let name = name.trim().to_lowercase();
+        let label = match name.as_str() {
+            "utf-8" | "utf8" | "65001" => return Ok(Self::Utf8),
+            "utf-16" | "utf-16le" | "utf16" | "unicode" | "widechar" | "1200" => {
+                return Ok(Self::Utf16)
+            }
+            n => match n.parse::<u32>() {
+                Ok(cp) => code_page_label(cp).ok_or_else(|| {
+                    LakeApi2SqlError::InvalidOptions(format!("code page {cp} is not supported"))
+                })?,
+                Err(_) => n.to_owned(),
+            },
+        };
+        match Encoding::for_label(label.as_bytes()) {
+            Some(e) if e == encoding_rs::UTF_8 => Ok(Self::Utf8),
+            // encoding_rs only decodes these
+            Some(e) if e == encoding_rs::UTF_16BE || e == encoding_rs::REPLACEMENT => Err(
+                LakeApi2SqlError::InvalidOptions(format!("cannot write {}", e.name())),
+            ),
+            Some(e) if e == encoding_rs::UTF_16LE => Ok(Self::Utf16),
+            Some(e) => Ok(Self::CodePage(e)),
+            None => Err(LakeApi2SqlError::InvalidOptions(format!(
+                "unknown encoding {name}"
+            ))),
+        }
+    }
+}
+
+/// Label of the encoding of a Windows code page
+fn code_page_label(cp: u32) -> Option<String> {
+    Some(match cp {
+        874 | 1250..=1258 => format!("windows-{cp}"),
+        866 => "ibm866".to_owned(),
+        932 => "shift_jis".to_owned(),
+        936 => "gbk".to_owned(),
+        949 => "euc-kr".to_owned(),
+        950 => "big5".to_owned(),
+        20866 => "koi8-r".to_owned(),
+        21866 => "koi8-u".to_owned(),
+        28592..=28606 => format!("iso-8859-{}", cp - 28590),
+        54936 => "gb18030".to_owned(),
+        _ => return None,
+    })
+}
+
+/// How to write csv files
+#[derive(Debug, Clone)]
+pub struct CsvExportOptions {
+    pub delimiter: String,
+    pub row_terminator: String,
+    pub quote: char,
+    pub quoting: Quoting,
+    /// Written for null values, never quoted
+    pub null_value: String,
+    /// Whether the first row has the column names, BULK INSERT needs `FIRSTROW = 2` then
+    pub header: bool,
+    /// chrono format of date columns
+    pub date_format: String,
+    /// chrono format of datetime, smalldatetime and datetime2 columns
+    pub datetime_format: String,
+    /// chrono format of datetimeoffset columns, which are in UTC
+    pub datetimeoffset_format: String,
+    /// chrono format of time columns
+    pub time_format: String,
+    pub encoding: CsvEncoding,
+    /// Where to write a bcp format file describing the csv file
+    pub format_file: Option<PathBuf>,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ",".to_owned(),
+            row_terminator: "\r\n".to_owned(),
+            quote: '"',
+            quoting: Quoting::Minimal,
+            null_value: "".to_owned(),
+            header: true,
+            date_format: "%Y-%m-%d".to_owned(),
+            datetime_format: "%Y-%m-%d %H:%M:%S%.f".to_owned(),
+            datetimeoffset_format: "%Y-%m-%d %H:%M:%S%.f %:z".to_owned(),
+            time_format: "%H:%M:%S%.f".to_owned(),
+            encoding: CsvEncoding::Utf8,
+            format_file: None,
+        }
+    }
+}
+
+impl CsvExportOptions {
+    pub fn validate(&self) -> Result<(), LakeApi2SqlError> {
+        let invalid = |m: &str| Err(LakeApi2SqlError::InvalidOptions(m.to_owned()));
+        if self.delimiter.is_empty() || self.row_terminator.is_empty() {
+            return invalid("the delimiter and the row terminator must not be empty");
+        }
+        if self.row_terminator.contains(&self.delimiter)
+            || self.delimiter.contains(&self.row_terminator)
+        {
+            return invalid("the delimiter and the row terminator must differ");
+        }
+        if self.quoting != Quoting::Never
+            && (self.delimiter.contains(self.quote) || self.row_terminator.contains(self.quote))
+        {
+            return invalid("the quote must not be part of the delimiter or the row terminator");
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a terminator for a format file
+fn format_file_terminator(terminator: &str) -> String {
+    let mut res = String::with_capacity(terminator.len() + 2);
+    res.push('"');
+    for c in terminator.chars() {
+        match c {
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\0' => res.push_str("\\0"),
+            '\\' => res.push_str("\\\\"),
+            '"' => res.push_str("\\\""),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// A non-XML bcp format file. All fields are character data ending at the delimiter, the last one
+/// at the row terminator, and map to the table columns in the order of the query
+pub fn format_file(schema: &Schema, options: &CsvExportOptions) -> String {
+    let host_type = match options.encoding {
+        CsvEncoding::Utf16 => "SQLNCHAR",
+        _ => "SQLCHAR",
+    };
+    let n = schema.fields().len();
+    let mut res = format!("{FORMAT_FILE_VERSION}\r\n{n}\r\n");
+    for (i, f) in schema.fields().iter().enumerate() {
+        let terminator = if i + 1 == n {
+            &options.row_terminator
+        } else {
+            &options.delimiter
+        };
+        // a length of 0 means the field is only delimited by its terminator. The column name is
+        // only informational, but must not contain whitespace
+        let name: String = f
+            .name()
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        write!(
+            res,
+            "{}\t{host_type}\t0\t0\t{}\t{}\t{name}\t\"\"\r\n",
+            i + 1,
+            format_file_terminator(terminator),
+            i + 1,
+        )
+        .expect("writing to a string");
+    }
+    res
+}
+
+/// Writes the rows of record batches as csv
+struct CsvWriter<W: Write> {
+    out: W,
+    options: CsvExportOptions,
+    line: String,
+    value: String,
+    bytes: u64,
+}
+
+impl<W: Write> CsvWriter<W> {
+    fn push_value(&mut self, column: &str) -> Result<(), LakeApi2SqlError> {
+        let o = &self.options;
+        let quote = match o.quoting {
+            Quoting::All => true,
+            Quoting::Minimal => {
+                self.value == o.null_value
+                    || self.value.contains(&o.delimiter)
+                    || self.value.contains(o.quote)
+                    || self.value.contains(&o.row_terminator)
+                    || self.value.contains(['\r', '\n'])
+            }
+            Quoting::Never => {
+                if self.value.contains(&o.delimiter) || self.value.contains(&o.row_terminator) {
+                    return Err(LakeApi2SqlError::InvalidOptions(format!(
+                        "a value of {column} contains the delimiter or the row terminator, \
+                        which needs quoting"
+                    )));
+                }
+                false
+            }
+        };
+        if quote {
+            self.line.push(o.quote);
+            for c in self.value.chars() {
+                if c == o.quote {
+                    self.line.push(c);
+                }
+                self.line.push(c);
+            }
+            self.line.push(o.quote);
+        } else {
+            self.line.push_str(&self.value);
+        }
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), LakeApi2SqlError> {
+        self.line.push_str(&self.options.row_terminator);
+        let bytes = match self.options.encoding {
+            CsvEncoding::Utf8 => {
+                self.out.write_all(self.line.as_bytes())?;
+                self.line.len()
+            }
+            CsvEncoding::Utf16 => {
+                let encoded: Vec<u8> = self
+                    .line
+                    .encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect();
+                self.out.write_all(&encoded)?;
+                encoded.len()
+            }
+            CsvEncoding::CodePage(e) => {
+                let mut encoder = e.new_encoder();
+                let mut encoded = Vec::with_capacity(
+                    encoder
+                        .max_buffer_length_from_utf8_without_replacement(self.line.len())
+                        .unwrap_or(self.line.len() * 4),
+                );
+                let (res, _) = encoder.encode_from_utf8_to_vec_without_replacement(
+                    &self.line,
+                    &mut encoded,
+                    true,
+                );
+                if let EncoderResult::Unmappable(c) = res {
+                    return Err(LakeApi2SqlError::InvalidOptions(format!(
+                        "{c:?} cannot be written in {}",
+                        e.name()
+                    )));
+                }
+                self.out.write_all(&encoded)?;
+                encoded.len()
+            }
+        };
+        self.bytes += bytes as u64;
+        self.line.clear();
+        Ok(())
+    }
+
+    fn write_header(&mut self, schema: &Schema) -> Result<(), LakeApi2SqlError> {
+        if let CsvEncoding::Utf16 = self.options.encoding {
+            self.out.write_all(&[0xFF, 0xFE])?;
+            self.bytes += 2;
+        }
+        if !self.options.header {
+            return Ok(());
+        }
+        for (i, f) in schema.fields().iter().enumerate() {
+            if i > 0 {
+                self.line.push_str(&self.options.delimiter);
+            }
+            self.value.clear();
+            self.value.push_str(f.name());
+            self.push_value(f.name())?;
+        }
+        self.end_line()
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), LakeApi2SqlError> {
+        let schema = batch.schema();
+        let columns = schema
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .map(|(f, a)| {
+                Ok(if is_uuid(f) {
+                    uuid_strings(a)
+                } else if a.data_type() == &DataType::Boolean {
+                    // bcp writes bits as 1 and 0
+                    cast(a, &DataType::UInt8)?
+                } else {
+                    a.clone()
+                })
+            })
+            .collect::<Result<Vec<ArrayRef>, LakeApi2SqlError>>()?;
+        let o = self.options.clone();
+        let format = FormatOptions::new()
+            .with_date_format(Some(o.date_format.as_str()))
+            .with_timestamp_format(Some(o.datetime_format.as_str()))
+            .with_timestamp_tz_format(Some(o.datetimeoffset_format.as_str()))
+            .with_time_format(Some(o.time_format.as_str()));
+        let formatters = columns
+            .iter()
+            .map(|c| ArrayFormatter::try_new(c.as_ref(), &format))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            for (i, (c, f)) in columns.iter().zip(formatters.iter()).enumerate() {
+                if i > 0 {
+                    self.line.push_str(&self.options.delimiter);
+                }
+                if c.is_null(row) {
+                    self.line.push_str(&self.options.null_value);
+                    continue;
+                }
+                self.value.clear();
+                f.value(row).write(&mut self.value)?;
+                self.push_value(schema.field(i).name())?;
+            }
+            self.end_line()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes all batches of the channel to a csv file and the format file, if there should be one,
+/// for [`crate::export::export_query`]
+pub fn write_csv(
+    path: &Path,
+    options: CsvExportOptions,
+    mut rx: mpsc::Receiver<RecordBatch>,
+) -> Result<Written, LakeApi2SqlError> {
+    let mut written = Written::default();
+    // the query failed before it returned anything
+    let Some(first) = rx.blocking_recv() else {
+        return Ok(written);
+    };
+    if let Some(format_path) = &options.format_file {
+        let content = format_file(&first.schema(), &options);
+        std::fs::write(format_path, &content)?;
+        written.files.push(format_path.clone());
+        written.bytes += content.len() as u64;
+    }
+    written.files.push(path.to_owned());
+    let mut writer = CsvWriter {
+        out: BufWriter::new(File::create(path)?),
+        options,
+        line: String::new(),
+        value: String::new(),
+        bytes: 0,
+    };
+    writer.write_header(&first.schema())?;
+    writer.write(&first)?;
+    while let Some(batch) = rx.blocking_recv() {
+        writer.write(&batch)?;
+    }
+    writer.out.flush()?;
+    written.bytes += writer.bytes;
+    Ok(written)
+}
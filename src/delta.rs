@@ -0,0 +1,522 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, ArrayRef, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde_json::Value;
+
+use crate::error::LakeApi2SqlError;
+
+/// Which version of a delta table to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeltaVersion {
+    #[default]
+    Latest,
+    Version(i64),
+    /// The last version committed at or before the given time, in milliseconds since the epoch
+    Timestamp(i64),
+}
+
+/// An active data file of a delta table
+#[derive(Debug, Clone)]
+pub struct DeltaFile {
+    pub path: PathBuf,
+    /// None for null partition values
+    pub partition_values: HashMap<String, Option<String>>,
+}
+
+/// The state of a delta table at one version
+#[derive(Debug, Clone)]
+pub struct DeltaSnapshot {
+    pub version: i64,
+    pub schema: SchemaRef,
+    pub partition_columns: Vec<String>,
+    pub files: Vec<DeltaFile>,
+}
+
+pub(crate) fn delta_error(msg: impl Into<String>) -> LakeApi2SqlError {
+    LakeApi2SqlError::DeltaError(msg.into())
+}
+
+/// Converts a type of the delta schema, see https://github.com/delta-io/delta/blob/master/PROTOCOL.md#schema-serialization-format
+fn delta_type_to_arrow(t: &Value) -> Result<DataType, LakeApi2SqlError> {
+    if let Some(name) = t.as_str() {
+        return Ok(match name {
+            "string" => DataType::Utf8,
+            "long" => DataType::Int64,
+            "integer" => DataType::Int32,
+            "short" => DataType::Int16,
+            "byte" => DataType::Int8,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "boolean" => DataType::Boolean,
+            "binary" => DataType::Binary,
+            "date" => DataType::Date32,
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            d if d.starts_with("decimal(") => {
+                let (p, s) = d[8..]
+                    .trim_end_matches(')')
+                    .split_once(',')
+                    .ok_or_else(|| delta_error(format!("invalid type {d}")))?;
+                DataType::Decimal128(
+                    p.trim()
+                        .parse()
+                        .map_err(|_| delta_error(format!("invalid type {d}")))?,
+                    s.trim()
+                        .parse()
+                        .map_err(|_| delta_error(format!("invalid type {d}")))?,
+                )
+            }
+            other => return Err(delta_error(format!("unsupported type {other}"))),
+        });
+    }
+    match t["type"].as_str() {
+        Some("struct") => Ok(DataType::Struct(delta_fields_to_arrow(&t["fields"])?)),
+        Some("array") => Ok(DataType::List(Arc::new(Field::new(
+            "element",
+            delta_type_to_arrow(&t["elementType"])?,
+            t["containsNull"].as_bool().unwrap_or(true),
+        )))),
+        Some("map") => Ok(DataType::Map(
+            Arc::new(Field::new(
+                "entries",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("key", delta_type_to_arrow(&t["keyType"])?, false),
+                    Field::new(
+                        "value",
+                        delta_type_to_arrow(&t["valueType"])?,
+                        t["valueContainsNull"].as_bool().unwrap_or(true),
+                    ),
+                ])),
+                false,
+            )),
+            false,
+        )),
+        _ => Err(delta_error(format!("unsupported type {t}"))),
+    }
+}
+
+fn delta_fields_to_arrow(fields: &Value) -> Result<Fields, LakeApi2SqlError> {
+    fields
+        .as_array()
+        .ok_or_else(|| delta_error("schema has no fields"))?
+        .iter()
+        .map(|f| {
+            Ok(Field::new(
+                f["name"]
+                    .as_str()
+                    .ok_or_else(|| delta_error("field without name"))?,
+                delta_type_to_arrow(&f["type"])?,
+                f["nullable"].as_bool().unwrap_or(true),
+            ))
+        })
+        .collect()
+}
+
+/// Paths in the log are relative and url encoded, eg `year=2024/part-00000.parquet`
+fn resolve_path(table: &Path, path: &str) -> Result<PathBuf, LakeApi2SqlError> {
+    let decoded = percent_decode(path);
+    if let Some(abs) = decoded.strip_prefix("file://") {
+        return Ok(PathBuf::from(abs));
+    }
+    if decoded.contains("://") {
+        return Err(delta_error(format!(
+            "only local files are supported: {path}"
+        )));
+    }
+    Ok(table.join(decoded))
+}
+
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                res.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// Log files of a table by version: the json commits and the latest checkpoints
+struct DeltaLog {
+    dir: PathBuf,
+    commits: Vec<i64>,
+    /// version and the files of a (possibly multi part) checkpoint
+    checkpoints: HashMap<i64, Vec<PathBuf>>,
+}
+
+impl DeltaLog {
+    fn list(table: &Path) -> Result<Self, LakeApi2SqlError> {
+        let dir = table.join("_delta_log");
+        if !dir.is_dir() {
+            return Err(delta_error(format!(
+                "{} is not a delta table",
+                table.display()
+            )));
+        }
+        let mut commits = vec![];
+        let mut checkpoints: HashMap<i64, Vec<PathBuf>> = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let Some((version, rest)) = name.split_once('.') else {
+                continue;
+            };
+            let Ok(version) = version.parse::<i64>() else {
+                continue;
+            };
+            if rest == "json" {
+                commits.push(version);
+            } else if rest.starts_with("checkpoint") && rest.ends_with(".parquet") {
+                checkpoints.entry(version).or_default().push(path.clone());
+            }
+        }
+        commits.sort();
+        Ok(Self {
+            dir,
+            commits,
+            checkpoints,
+        })
+    }
+
+    fn commit_path(&self, version: i64) -> PathBuf {
+        self.dir.join(format!("{version:020}.json"))
+    }
+
+    fn latest(&self) -> Option<i64> {
+        self.commits
+            .last()
+            .copied()
+            .max(self.checkpoints.keys().max().copied())
+    }
+
+    /// Commit time by the `commitInfo` action, or the modification time of the file
+    fn commit_timestamp(&self, version: i64) -> Result<i64, LakeApi2SqlError> {
+        let path = self.commit_path(version);
+        for line in BufReader::new(File::open(&path)?).lines() {
+            let action: Value = serde_json::from_str(&line?)
+                .map_err(|e| delta_error(format!("invalid commit {version}: {e}")))?;
+            if let Some(ts) = action["commitInfo"]["timestamp"].as_i64() {
+                return Ok(ts);
+            }
+        }
+        let modified = std::fs::metadata(&path)?.modified()?;
+        Ok(modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default())
+    }
+
+    fn resolve(&self, version: DeltaVersion) -> Result<i64, LakeApi2SqlError> {
+        let latest = self
+            .latest()
+            .ok_or_else(|| delta_error("the table has no commits"))?;
+        match version {
+            DeltaVersion::Latest => Ok(latest),
+            DeltaVersion::Version(v) => {
+                if v > latest || (!self.commits.contains(&v) && !self.checkpoints.contains_key(&v))
+                {
+                    Err(delta_error(format!("version {v} does not exist")))
+                } else {
+                    Ok(v)
+                }
+            }
+            DeltaVersion::Timestamp(ts) => {
+                let mut found = None;
+                for v in self.commits.iter() {
+                    if self.commit_timestamp(*v)? > ts {
+                        break;
+                    }
+                    found = Some(*v);
+                }
+                found.ok_or_else(|| delta_error(format!("no version committed before {ts}")))
+            }
+        }
+    }
+}
+
+/// Applies the actions of one commit or checkpoint row
+#[derive(Default)]
+struct LogReplay {
+    metadata: Option<Value>,
+    protocol: Option<Value>,
+    files: HashMap<String, Value>,
+}
+
+impl LogReplay {
+    fn apply(&mut self, action: &Value) {
+        if let Some(add) = action.get("add").filter(|a| !a.is_null()) {
+            if let Some(path) = add["path"].as_str() {
+                self.files.insert(path.to_owned(), add.clone());
+            }
+        }
+        if let Some(remove) = action.get("remove").filter(|a| !a.is_null()) {
+            if let Some(path) = remove["path"].as_str() {
+                self.files.remove(path);
+            }
+        }
+        if let Some(m) = action.get("metaData").filter(|a| !a.is_null()) {
+            self.metadata = Some(m.clone());
+        }
+        if let Some(p) = action.get("protocol").filter(|a| !a.is_null()) {
+            self.protocol = Some(p.clone());
+        }
+    }
+
+    fn apply_checkpoint(&mut self, path: &Path) -> Result<(), LakeApi2SqlError> {
+        let reader = SerializedFileReader::new(File::open(path)?)?;
+        for row in reader.get_row_iter(None)? {
+            let mut action = row?.to_json_value();
+            // map keys are rendered like display values by the record api, ie strings are quoted
+            if let Some(Value::Object(pv)) = action
+                .get_mut("add")
+                .and_then(|a| a.get_mut("partitionValues"))
+            {
+                *pv = std::mem::take(pv)
+                    .into_iter()
+                    .map(|(k, v)| (k.trim_matches('"').to_owned(), v))
+                    .collect();
+            }
+            self.apply(&action);
+        }
+        Ok(())
+    }
+
+    fn apply_commit(&mut self, path: &Path) -> Result<(), LakeApi2SqlError> {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let action: Value = serde_json::from_str(&line)
+                .map_err(|e| delta_error(format!("invalid commit {}: {e}", path.display())))?;
+            self.apply(&action);
+        }
+        Ok(())
+    }
+}
+
+/// The raw state of a table after replaying its log
+pub(crate) struct TableState {
+    pub version: i64,
+    pub metadata: Value,
+    pub protocol: Option<Value>,
+    /// The add actions of the active files by their path in the log
+    pub files: HashMap<String, Value>,
+}
+
+/// The latest version of a table, None if there is no `_delta_log` with commits yet
+pub(crate) fn latest_version(table: &Path) -> Result<Option<i64>, LakeApi2SqlError> {
+    if !table.join("_delta_log").is_dir() {
+        return Ok(None);
+    }
+    Ok(DeltaLog::list(table)?.latest())
+}
+
+/// Replays the `_delta_log` of a local table, the newest checkpoint not after the requested
+/// version is used as starting point
+pub(crate) fn replay_log(
+    table: &Path,
+    version: DeltaVersion,
+) -> Result<TableState, LakeApi2SqlError> {
+    let log = DeltaLog::list(table)?;
+    let version = log.resolve(version)?;
+    let mut replay = LogReplay::default();
+    let checkpoint = log
+        .checkpoints
+        .keys()
+        .filter(|v| **v <= version)
+        .max()
+        .copied();
+    if let Some(cp) = checkpoint {
+        for part in log.checkpoints[&cp].iter() {
+            replay.apply_checkpoint(part)?;
+        }
+    }
+    for v in log
+        .commits
+        .iter()
+        .filter(|v| Some(**v) > checkpoint && **v <= version)
+    {
+        replay.apply_commit(&log.commit_path(*v))?;
+    }
+    Ok(TableState {
+        version,
+        metadata: replay
+            .metadata
+            .ok_or_else(|| delta_error("no metaData action in the log"))?,
+        protocol: replay.protocol,
+        files: replay.files,
+    })
+}
+
+/// The arrow schema of the `schemaString` of a metaData action
+pub(crate) fn metadata_schema(metadata: &Value) -> Result<Schema, LakeApi2SqlError> {
+    let schema: Value = serde_json::from_str(metadata["schemaString"].as_str().unwrap_or_default())
+        .map_err(|e| delta_error(format!("invalid schema: {e}")))?;
+    Ok(Schema::new(delta_fields_to_arrow(&schema["fields"])?))
+}
+
+/// Reads the `_delta_log` of a local table
+pub fn read_snapshot(
+    table: &Path,
+    version: DeltaVersion,
+) -> Result<DeltaSnapshot, LakeApi2SqlError> {
+    let state = replay_log(table, version)?;
+
+    if let Some(protocol) = &state.protocol {
+        for feature in protocol["readerFeatures"].as_array().into_iter().flatten() {
+            match feature.as_str() {
+                Some("timestampNtz") | Some("deletionVectors") | Some("vacuumProtocolCheck") => {}
+                f => return Err(delta_error(format!("unsupported reader feature {f:?}"))),
+            }
+        }
+    }
+    let metadata = state.metadata;
+    if let Some(mode) = metadata["configuration"]["delta.columnMapping.mode"].as_str() {
+        if mode != "none" {
+            return Err(delta_error(format!(
+                "column mapping mode {mode} is not supported"
+            )));
+        }
+    }
+    let schema = Arc::new(metadata_schema(&metadata)?);
+    let partition_columns = metadata["partitionColumns"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.as_str().map(|s| s.to_owned()))
+        .collect();
+
+    let mut files = state
+        .files
+        .into_iter()
+        .map(|(path, add)| {
+            if !add["deletionVector"].is_null() {
+                return Err(delta_error(format!(
+                    "deletion vectors are not supported: {path}"
+                )));
+            }
+            let partition_values = add["partitionValues"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), v.as_str().map(|s| s.to_owned())))
+                .collect();
+            Ok(DeltaFile {
+                path: resolve_path(table, &path)?,
+                partition_values,
+            })
+        })
+        .collect::<Result<Vec<_>, LakeApi2SqlError>>()?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(DeltaSnapshot {
+        version: state.version,
+        schema,
+        partition_columns,
+        files,
+    })
+}
+
+/// Reads the files of a snapshot one after the other. Partition values are added as columns and
+/// the batches are cast to the table schema, columns missing in older files are null
+pub struct DeltaReader {
+    schema: SchemaRef,
+    partition_columns: Vec<String>,
+    files: VecDeque<DeltaFile>,
+    current: Option<(ParquetRecordBatchReader, DeltaFile)>,
+}
+
+impl DeltaReader {
+    pub fn new(snapshot: DeltaSnapshot) -> Self {
+        Self {
+            schema: snapshot.schema,
+            partition_columns: snapshot.partition_columns,
+            files: snapshot.files.into(),
+            current: None,
+        }
+    }
+}
+
+fn to_table_schema(
+    schema: &SchemaRef,
+    partition_columns: &[String],
+    batch: RecordBatch,
+    file: &DeltaFile,
+) -> Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| -> Result<ArrayRef, ArrowError> {
+            if partition_columns.contains(field.name()) {
+                let value = file.partition_values.get(field.name()).cloned().flatten();
+                let values: ArrayRef = Arc::new(StringArray::from(vec![value; batch.num_rows()]));
+                return cast(&values, field.data_type());
+            }
+            match batch.column_by_name(field.name()) {
+                Some(c) if c.data_type() == field.data_type() => Ok(c.clone()),
+                Some(c) => cast(c, field.data_type()),
+                None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+            }
+        })
+        .collect::<Result<Vec<_>, ArrowError>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+impl Iterator for DeltaReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((reader, file)) = self.current.as_mut() {
+                match reader.next() {
+                    Some(Ok(batch)) => {
+                        return Some(to_table_schema(
+                            &self.schema,
+                            &self.partition_columns,
+                            batch,
+                            file,
+                        ))
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => self.current = None,
+                }
+            }
+            let file = self.files.pop_front()?;
+            let reader = File::open(&file.path)
+                .map_err(ArrowError::from)
+                .and_then(|f| Ok(ParquetRecordBatchReaderBuilder::try_new(f)?.build()?));
+            match reader {
+                Ok(r) => self.current = Some((r, file)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl RecordBatchReader for DeltaReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
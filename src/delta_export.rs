@@ -0,0 +1,633 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow::array::{Array, ArrayRef, AsArray, StringArray, UInt32Array};
+use arrow::compute::{cast_with_options, take, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use serde_json::{json, Value};
+use tiberius::{Client, ToSql};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::Compat;
+
+use crate::delta::{
+    delta_error, latest_version, metadata_schema, percent_decode, replay_log, DeltaVersion,
+    TableState,
+};
+use crate::error::LakeApi2SqlError;
+use crate::export::{export_query, ExportResult, Written};
+use crate::parquet_export::{ParquetExportOptions, ParquetFilesWriter};
+use crate::sql_to_arrow::{is_uuid, uuid_strings};
+
+/// Directory name of null partition values
+const HIVE_NULL: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// What happens to the data already in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SaveMode {
+    #[default]
+    Append,
+    /// The files of the table are removed from the log, they stay on disk for time travel
+    Overwrite,
+}
+
+impl SaveMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "append" => Some(Self::Append),
+            "overwrite" => Some(Self::Overwrite),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Append => "Append",
+            Self::Overwrite => "Overwrite",
+        }
+    }
+}
+
+/// How to write a delta table
+#[derive(Debug, Clone, Default)]
+pub struct DeltaExportOptions {
+    pub mode: SaveMode,
+    /// Used for new tables and overwrites. Appends use the partition columns of the table, if given
+    /// they must be the same
+    pub partition_columns: Vec<String>,
+    /// How the data files are written
+    pub parquet: ParquetExportOptions,
+}
+
+fn arrow_type_to_delta(dt: &DataType) -> Result<String, LakeApi2SqlError> {
+    Ok(match dt {
+        DataType::Utf8 => "string".to_owned(),
+        DataType::Int64 => "long".to_owned(),
+        DataType::Int32 => "integer".to_owned(),
+        DataType::Int16 => "short".to_owned(),
+        DataType::Int8 => "byte".to_owned(),
+        DataType::Float32 => "float".to_owned(),
+        DataType::Float64 => "double".to_owned(),
+        DataType::Boolean => "boolean".to_owned(),
+        DataType::Binary => "binary".to_owned(),
+        DataType::Date32 => "date".to_owned(),
+        DataType::Timestamp(_, Some(_)) => "timestamp".to_owned(),
+        DataType::Timestamp(_, None) => "timestamp_ntz".to_owned(),
+        DataType::Decimal128(p, s) => format!("decimal({p},{s})"),
+        dt => return Err(delta_error(format!("unsupported type {dt}"))),
+    })
+}
+
+fn schema_string(schema: &Schema) -> Result<String, LakeApi2SqlError> {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|f| {
+            Ok(json!({
+                "name": f.name(),
+                "type": arrow_type_to_delta(f.data_type())?,
+                "nullable": true,
+                "metadata": {},
+            }))
+        })
+        .collect::<Result<Vec<_>, LakeApi2SqlError>>()?;
+    Ok(json!({"type": "struct", "fields": fields}).to_string())
+}
+
+/// Delta has no unsigned, uuid or time types: tinyint becomes short, uniqueidentifier and time
+/// become strings
+fn delta_field(field: &Field) -> Field {
+    let dt = match field.data_type() {
+        DataType::UInt8 => DataType::Int16,
+        DataType::FixedSizeBinary(_) | DataType::Time64(_) => DataType::Utf8,
+        DataType::Timestamp(_, tz) => DataType::Timestamp(TimeUnit::Microsecond, tz.clone()),
+        dt => dt.clone(),
+    };
+    Field::new(field.name(), dt, true)
+}
+
+fn to_delta_column(
+    field: &Field,
+    array: &ArrayRef,
+    target: &DataType,
+) -> Result<ArrayRef, LakeApi2SqlError> {
+    let array = if is_uuid(field) {
+        uuid_strings(array)
+    } else {
+        array.clone()
+    };
+    if array.data_type() == target {
+        return Ok(array);
+    }
+    // overflows fail instead of becoming null
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    Ok(cast_with_options(&array, target, &options)?)
+}
+
+/// Escapes a partition value for a directory name, like hive does
+fn escape_partition_value(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b' ') {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{b:02X}"));
+        }
+    }
+    res
+}
+
+/// Paths in the log are url encoded, the directory names only have spaces and escapes left
+fn encode_log_path(path: &str) -> String {
+    path.replace('%', "%25").replace(' ', "%20")
+}
+
+/// Checks that this writer can write to the table, it only knows the features of reader version 3
+/// and writer version 7 that don't need anything special on writes
+fn check_writable(state: &TableState, mode: SaveMode) -> Result<(), LakeApi2SqlError> {
+    let protocol = state.protocol.clone().unwrap_or(Value::Null);
+    let writer_version = protocol["minWriterVersion"].as_i64().unwrap_or(1);
+    if (3..7).contains(&writer_version) {
+        return Err(delta_error(format!(
+            "writer version {writer_version} is not supported"
+        )));
+    }
+    for feature in protocol["writerFeatures"].as_array().into_iter().flatten() {
+        match feature.as_str() {
+            Some("timestampNtz") | Some("appendOnly") | Some("invariants") => {}
+            f => return Err(delta_error(format!("unsupported writer feature {f:?}"))),
+        }
+    }
+    for feature in protocol["readerFeatures"].as_array().into_iter().flatten() {
+        if feature.as_str() != Some("timestampNtz") {
+            return Err(delta_error(format!("unsupported reader feature {feature}")));
+        }
+    }
+    let metadata = &state.metadata;
+    if metadata["schemaString"]
+        .as_str()
+        .unwrap_or_default()
+        .contains("delta.invariants")
+    {
+        return Err(delta_error("column invariants are not supported"));
+    }
+    if let Some(mode) = metadata["configuration"]["delta.columnMapping.mode"].as_str() {
+        if mode != "none" {
+            return Err(delta_error(format!(
+                "column mapping mode {mode} is not supported"
+            )));
+        }
+    }
+    if mode == SaveMode::Overwrite
+        && metadata["configuration"]["delta.appendOnly"].as_str() == Some("true")
+    {
+        return Err(delta_error("the table is append only"));
+    }
+    Ok(())
+}
+
+fn partition_columns_of(metadata: &Value) -> Vec<String> {
+    metadata["partitionColumns"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.as_str().map(|s| s.to_owned()))
+        .collect()
+}
+
+/// The schema of the data files. Appends cast the data to the types of the table, eg an int column
+/// to a long
+fn target_schema(
+    source: &Schema,
+    table_schema: Option<&Schema>,
+) -> Result<Schema, LakeApi2SqlError> {
+    let mut fields: Vec<Field> = source.fields().iter().map(|f| delta_field(f)).collect();
+    if let Some(table_schema) = table_schema {
+        let mut names: Vec<&String> = fields.iter().map(|f| f.name()).collect();
+        let mut table_names: Vec<&String> =
+            table_schema.fields().iter().map(|f| f.name()).collect();
+        names.sort();
+        table_names.sort();
+        if names != table_names {
+            return Err(LakeApi2SqlError::SchemaMismatch(format!(
+                "the query returns the columns {names:?}, the table has {table_names:?}"
+            )));
+        }
+        for f in fields.iter_mut() {
+            let table_field = table_schema.field_with_name(f.name())?;
+            *f = Field::new(f.name(), table_field.data_type().clone(), true);
+        }
+    }
+    for f in fields.iter() {
+        arrow_type_to_delta(f.data_type())?;
+    }
+    Ok(Schema::new(fields))
+}
+
+/// Writes the data files, one set of files per partition
+struct PartitionedWriter {
+    table: PathBuf,
+    partition_columns: Vec<String>,
+    parquet: ParquetExportOptions,
+    /// The table schema appends have to match
+    table_schema: Option<Schema>,
+    /// Schema of the batches after the conversion to delta types
+    schema: Option<SchemaRef>,
+    partition_indices: Vec<usize>,
+    data_indices: Vec<usize>,
+    writers: HashMap<Vec<Option<String>>, ParquetFilesWriter>,
+    /// Partitions in the order they were seen, so the files are listed in a stable order
+    partitions: Vec<Vec<Option<String>>>,
+}
+
+impl PartitionedWriter {
+    /// Checks the schema of the first batch against the table and the partition columns
+    fn init(&mut self, source: &Schema) -> Result<SchemaRef, LakeApi2SqlError> {
+        let schema = Arc::new(target_schema(source, self.table_schema.as_ref())?);
+        self.partition_indices = self
+            .partition_columns
+            .iter()
+            .map(|c| {
+                let i = schema.index_of(c).map_err(|_| {
+                    LakeApi2SqlError::InvalidOptions(format!("partition column {c} not found"))
+                })?;
+                match schema.field(i).data_type() {
+                    DataType::Utf8
+                    | DataType::Int8
+                    | DataType::Int16
+                    | DataType::Int32
+                    | DataType::Int64
+                    | DataType::Boolean
+                    | DataType::Date32 => Ok(i),
+                    dt => Err(LakeApi2SqlError::InvalidOptions(format!(
+                        "partition column {c} has the unsupported type {dt}"
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>, LakeApi2SqlError>>()?;
+        self.data_indices = (0..schema.fields().len())
+            .filter(|i| !self.partition_indices.contains(i))
+            .collect();
+        self.schema = Some(schema.clone());
+        Ok(schema)
+    }
+
+    fn writer(
+        &mut self,
+        key: &[Option<String>],
+    ) -> Result<&mut ParquetFilesWriter, LakeApi2SqlError> {
+        if !self.writers.contains_key(key) {
+            let mut dir = self.table.clone();
+            for (c, v) in self.partition_columns.iter().zip(key.iter()) {
+                let v = v.as_deref().map(escape_partition_value);
+                dir.push(format!("{c}={}", v.as_deref().unwrap_or(HIVE_NULL)));
+            }
+            std::fs::create_dir_all(&dir)?;
+            let path = dir.join(format!("part-{}.parquet", uuid::Uuid::new_v4()));
+            self.writers.insert(
+                key.to_vec(),
+                ParquetFilesWriter::new(&path, self.parquet.clone()),
+            );
+            self.partitions.push(key.to_vec());
+        }
+        Ok(self.writers.get_mut(key).expect("inserted above"))
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), LakeApi2SqlError> {
+        let schema = match &self.schema {
+            Some(s) => s.clone(),
+            None => self.init(&batch.schema())?,
+        };
+        let columns = batch
+            .schema()
+            .fields()
+            .iter()
+            .zip(batch.columns())
+            .zip(schema.fields().iter())
+            .map(|((f, a), t)| to_delta_column(f, a, t.data_type()))
+            .collect::<Result<Vec<_>, LakeApi2SqlError>>()?;
+        let batch = RecordBatch::try_new(schema, columns)?;
+        let data = batch.project(&self.data_indices)?;
+        if self.partition_indices.is_empty() {
+            return self.writer(&[])?.write(&data);
+        }
+
+        let values = self
+            .partition_indices
+            .iter()
+            .map(|i| {
+                Ok(cast_with_options(
+                    batch.column(*i),
+                    &DataType::Utf8,
+                    &CastOptions::default(),
+                )?)
+            })
+            .collect::<Result<Vec<ArrayRef>, LakeApi2SqlError>>()?;
+        let values: Vec<&StringArray> = values.iter().map(|v| v.as_string::<i32>()).collect();
+        let mut rows: HashMap<Vec<Option<String>>, Vec<u32>> = HashMap::new();
+        let mut keys = vec![];
+        for row in 0..batch.num_rows() {
+            let key: Vec<Option<String>> = values
+                .iter()
+                .map(|v| v.is_valid(row).then(|| v.value(row).to_owned()))
+                .collect();
+            rows.entry(key.clone())
+                .or_insert_with(|| {
+                    keys.push(key);
+                    vec![]
+                })
+                .push(row as u32);
+        }
+        for key in keys {
+            let indices = UInt32Array::from(rows.remove(&key).unwrap_or_default());
+            let columns = data
+                .columns()
+                .iter()
+                .map(|c| take(c, &indices, None))
+                .collect::<Result<Vec<_>, _>>()?;
+            let part = RecordBatch::try_new(data.schema(), columns)?;
+            self.writer(&key)?.write(&part)?;
+        }
+        Ok(())
+    }
+
+    fn write_all(
+        mut self,
+        mut rx: mpsc::Receiver<RecordBatch>,
+    ) -> Result<Written, LakeApi2SqlError> {
+        while let Some(batch) = rx.blocking_recv() {
+            self.write(&batch)?;
+        }
+        let mut res = Written::default();
+        for key in self.partitions.iter() {
+            let writer = self
+                .writers
+                .remove(key)
+                .expect("a writer for each partition");
+            let written = writer.finish()?;
+            res.files.extend(written.files);
+            res.bytes += written.bytes;
+        }
+        Ok(res)
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// The add action of a data file, partition values are taken from the directory names
+fn add_action(table: &Path, file: &Path) -> Result<Value, LakeApi2SqlError> {
+    let relative = file
+        .strip_prefix(table)
+        .map_err(|_| delta_error(format!("{} is not in the table", file.display())))?;
+    let mut partition_values = serde_json::Map::new();
+    let components: Vec<String> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    for dir in components.iter().take(components.len().saturating_sub(1)) {
+        if let Some((column, value)) = dir.split_once('=') {
+            let value = match value {
+                HIVE_NULL => Value::Null,
+                v => Value::String(percent_decode(v)),
+            };
+            partition_values.insert(column.to_owned(), value);
+        }
+    }
+    let metadata = std::fs::metadata(file)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    Ok(json!({"add": {
+        "path": encode_log_path(&components.join("/")),
+        "partitionValues": partition_values,
+        "size": metadata.len(),
+        "modificationTime": modified,
+        "dataChange": true,
+    }}))
+}
+
+/// Protocol with the timestampNtz feature, legacy writer features of version 2 get listed explicitly
+fn ntz_protocol(existing: Option<&Value>) -> Value {
+    let mut reader_features: Vec<Value> = vec![];
+    let mut writer_features: Vec<Value> = vec![];
+    if let Some(p) = existing {
+        if p["minWriterVersion"].as_i64().unwrap_or(1) >= 7 {
+            reader_features.extend(p["readerFeatures"].as_array().cloned().unwrap_or_default());
+            writer_features.extend(p["writerFeatures"].as_array().cloned().unwrap_or_default());
+        } else if p["minWriterVersion"].as_i64().unwrap_or(1) >= 2 {
+            writer_features.extend([json!("appendOnly"), json!("invariants")]);
+        }
+    }
+    for features in [&mut reader_features, &mut writer_features] {
+        if !features.contains(&json!("timestampNtz")) {
+            features.push(json!("timestampNtz"));
+        }
+    }
+    json!({"protocol": {
+        "minReaderVersion": 3,
+        "minWriterVersion": 7,
+        "readerFeatures": reader_features,
+        "writerFeatures": writer_features,
+    }})
+}
+
+fn has_ntz(protocol: Option<&Value>) -> bool {
+    protocol
+        .and_then(|p| p["readerFeatures"].as_array())
+        .is_some_and(|f| f.contains(&json!("timestampNtz")))
+}
+
+/// Writes the next commit. Fails if another writer committed the same version in the meantime
+fn commit(
+    table: &Path,
+    state: Option<&TableState>,
+    mode: SaveMode,
+    partition_columns: &[String],
+    schema: &Schema,
+    files: &[PathBuf],
+) -> Result<i64, LakeApi2SqlError> {
+    let version = state.map(|s| s.version + 1).unwrap_or_default();
+    let now = now_ms();
+    let mut actions = vec![json!({"commitInfo": {
+        "timestamp": now,
+        "operation": "WRITE",
+        "operationParameters": {
+            "mode": mode.as_str(),
+            "partitionBy": serde_json::to_string(partition_columns).unwrap_or_default(),
+        },
+        "engineInfo": concat!("lakeapi2sql ", env!("CARGO_PKG_VERSION")),
+    }})];
+    let data_schema = Schema::new(
+        schema
+            .fields()
+            .iter()
+            .filter(|f| !partition_columns.contains(f.name()))
+            .chain(
+                partition_columns
+                    .iter()
+                    .filter_map(|c| schema.field_with_name(c).ok()),
+            )
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+    let needs_ntz = schema
+        .fields()
+        .iter()
+        .any(|f| matches!(f.data_type(), DataType::Timestamp(_, None)));
+    let schema_string = schema_string(&data_schema)?;
+    match state {
+        None => {
+            actions.push(if needs_ntz {
+                ntz_protocol(None)
+            } else {
+                json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}})
+            });
+            actions.push(json!({"metaData": {
+                "id": uuid::Uuid::new_v4().to_string(),
+                "format": {"provider": "parquet", "options": {}},
+                "schemaString": schema_string,
+                "partitionColumns": partition_columns,
+                "configuration": {},
+                "createdTime": now,
+            }}));
+        }
+        Some(state) => {
+            if needs_ntz && !has_ntz(state.protocol.as_ref()) {
+                actions.push(ntz_protocol(state.protocol.as_ref()));
+            }
+            if mode == SaveMode::Overwrite {
+                let mut metadata = state.metadata.clone();
+                if metadata_schema(&metadata)?
+                    != metadata_schema(&json!({"schemaString": schema_string}))?
+                    || partition_columns_of(&metadata) != partition_columns
+                {
+                    metadata["schemaString"] = json!(schema_string);
+                    metadata["partitionColumns"] = json!(partition_columns);
+                    actions.push(json!({ "metaData": metadata }));
+                }
+                for (path, add) in state.files.iter() {
+                    actions.push(json!({"remove": {
+                        "path": path,
+                        "deletionTimestamp": now,
+                        "dataChange": true,
+                        "extendedFileMetadata": true,
+                        "partitionValues": add["partitionValues"],
+                        "size": add["size"],
+                    }}));
+                }
+            }
+        }
+    }
+    for file in files {
+        actions.push(add_action(table, file)?);
+    }
+
+    let mut content = String::new();
+    for action in actions {
+        content.push_str(&action.to_string());
+        content.push('\n');
+    }
+    let log_dir = table.join("_delta_log");
+    std::fs::create_dir_all(&log_dir)?;
+    let path = log_dir.join(format!("{version:020}.json"));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                delta_error(format!("version {version} was committed by another writer"))
+            }
+            _ => e.into(),
+        })?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(version)
+}
+
+/// Runs the query and writes the result into a local delta table, which is created if needed.
+/// The data files are written first, the commit makes them visible once the query succeeded
+pub async fn export_query_delta(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    batch_rows: usize,
+    table: &Path,
+    options: DeltaExportOptions,
+) -> Result<ExportResult, LakeApi2SqlError> {
+    let state = match latest_version(table)? {
+        Some(_) => Some(replay_log(table, DeltaVersion::Latest)?),
+        None => None,
+    };
+    let mut partition_columns = options.partition_columns.clone();
+    let mut table_schema = None;
+    if let Some(state) = &state {
+        check_writable(state, options.mode)?;
+        let table_partitions = partition_columns_of(&state.metadata);
+        if options.mode == SaveMode::Append {
+            if !partition_columns.is_empty() && partition_columns != table_partitions {
+                return Err(LakeApi2SqlError::InvalidOptions(format!(
+                    "the table is partitioned by {table_partitions:?}"
+                )));
+            }
+            partition_columns = table_partitions;
+            table_schema = Some(metadata_schema(&state.metadata)?);
+        } else if partition_columns.is_empty() {
+            partition_columns = table_partitions;
+        }
+    }
+
+    let writer = PartitionedWriter {
+        table: table.to_owned(),
+        partition_columns: partition_columns.clone(),
+        parquet: options.parquet.clone(),
+        table_schema: table_schema.clone(),
+        schema: None,
+        partition_indices: vec![],
+        data_indices: vec![],
+        writers: HashMap::new(),
+        partitions: vec![],
+    };
+    let res = export_query(client, query, params, batch_rows, move |rx| {
+        writer.write_all(rx)
+    })
+    .await?;
+
+    let schema = target_schema(&res.schema, table_schema.as_ref())?;
+    let table = table.to_owned();
+    let files = res.files.clone();
+    let mode = options.mode;
+    let commit_res = tokio::task::spawn_blocking(move || {
+        commit(
+            &table,
+            state.as_ref(),
+            mode,
+            &partition_columns,
+            &schema,
+            &files,
+        )
+    })
+    .await?;
+    if let Err(e) = commit_res {
+        for f in res.files.iter() {
+            let _ = std::fs::remove_file(f);
+        }
+        return Err(e);
+    }
+    Ok(res)
+}
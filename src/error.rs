@@ -16,6 +16,9 @@ pub enum LakeApi2SqlError {
     #[error("Arrow Error: {0}")]
     ArrowError(#[from] arrow::error::ArrowError),
 
+    #[error("Parquet Error: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
+
     #[error("IO Error: {0}")]
     IOError(#[from] std::io::Error),
 
@@ -27,6 +30,30 @@ pub enum LakeApi2SqlError {
 
     #[error(transparent)]
     TiberiusError(#[from] tiberius::error::Error),
+
+    #[error("Watermark column not found: {0}")]
+    WatermarkColumnNotFound(String),
+
+    #[error("Unsupported watermark value: {0}")]
+    UnsupportedWatermark(String),
+
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
+    #[error("Invalid checkpoint file: {0}")]
+    InvalidCheckpoint(String),
+
+    #[error("Invalid options: {0}")]
+    InvalidOptions(String),
+
+    #[error("Authentication failed: {0}")]
+    AuthError(String),
+
+    #[error("Schema mismatch: {0}")]
+    SchemaMismatch(String),
+
+    #[error("Delta table error: {0}")]
+    DeltaError(String),
 }
 
 impl From<LakeApi2SqlError> for PyErr {
@@ -38,10 +65,31 @@ impl From<LakeApi2SqlError> for PyErr {
             } => PyErr::new::<PyTypeError, _>(format!("{:?}", v)),
             LakeApi2SqlError::JoinError(e) => PyErr::new::<PyIOError, _>(format!("{:?}", e)),
             LakeApi2SqlError::ArrowError(e) => PyErr::new::<PyValueError, _>(format!("{:?}", e)),
+            LakeApi2SqlError::ParquetError(e) => PyErr::new::<PyValueError, _>(format!("{:?}", e)),
             LakeApi2SqlError::IOError(e) => PyErr::new::<PyIOError, _>(format!("{:?}", e)),
             LakeApi2SqlError::HttpError(e) => PyErr::new::<PyIOError, _>(format!("{:?}", e)),
             LakeApi2SqlError::SendError(e) => PyErr::new::<PyIOError, _>(format!("{:?}", e)),
             LakeApi2SqlError::TiberiusError(e) => PyErr::new::<PyIOError, _>(format!("{:?}", e)),
+            v @ LakeApi2SqlError::WatermarkColumnNotFound(_) => {
+                PyErr::new::<PyValueError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::UnsupportedWatermark(_) => {
+                PyErr::new::<PyTypeError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::Timeout(_) => PyErr::new::<PyTimeoutError, _>(format!("{:?}", v)),
+            v @ LakeApi2SqlError::InvalidCheckpoint(_) => {
+                PyErr::new::<PyValueError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::InvalidOptions(_) => {
+                PyErr::new::<PyValueError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::AuthError(_) => {
+                PyErr::new::<PyPermissionError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::SchemaMismatch(_) => {
+                PyErr::new::<PyValueError, _>(format!("{:?}", v))
+            }
+            v @ LakeApi2SqlError::DeltaError(_) => PyErr::new::<PyIOError, _>(format!("{:?}", v)),
         }
     }
 }
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use tiberius::{Client, ToSql};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::Compat;
+
+use crate::error::LakeApi2SqlError;
+use crate::sql_to_arrow::query_batches;
+
+/// Rows per record batch read from the server
+pub const DEFAULT_BATCH_ROWS: usize = 8192;
+
+/// Result of an export
+#[derive(Debug, Clone)]
+pub struct ExportResult {
+    /// Schema of the query result
+    pub schema: SchemaRef,
+    pub rows: u64,
+    /// The files written, empty if the target is not a local file
+    pub files: Vec<PathBuf>,
+    /// Bytes written to all files or sent to the target
+    pub bytes: u64,
+}
+
+/// What a writer of an export wrote
+#[derive(Debug, Clone, Default)]
+pub struct Written {
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+/// Names the files of a split export `<stem>-00000.<ext>`, `<stem>-00001.<ext>` and so on
+pub fn split_file_name(path: &Path, index: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{index:05}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{index:05}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Runs the query and hands the batches to `write`, which runs on a blocking thread while the
+/// next rows are read. Only two batches are buffered, so memory stays bounded for any size of
+/// result. If the query fails, the files the writer wrote are removed again
+pub async fn export_query<F>(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    batch_rows: usize,
+    write: F,
+) -> Result<ExportResult, LakeApi2SqlError>
+where
+    F: FnOnce(mpsc::Receiver<RecordBatch>) -> Result<Written, LakeApi2SqlError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(2);
+    let writer = tokio::task::spawn_blocking(move || write(rx));
+    let read = query_batches(client, query, params, batch_rows, tx).await;
+    // a failing writer drops the receiver, its error is the cause of the send error of the reader
+    let written = writer.await??;
+    match read {
+        Ok((schema, rows)) => Ok(ExportResult {
+            schema,
+            rows,
+            files: written.files,
+            bytes: written.bytes,
+        }),
+        Err(e) => {
+            for f in written.files {
+                if let Err(re) = std::fs::remove_file(&f) {
+                    log::warn!("could not remove {}: {re}", f.display());
+                }
+            }
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+
+use crate::error::LakeApi2SqlError;
+use crate::source_format::{open_file, DataFormat, ReadOptions};
+
+/// Local files to load, all files must have the same schema
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    /// A path or a glob pattern like `data/**/*.parquet`
+    pub pattern: String,
+    /// Detected by the file extension if None
+    pub format: Option<DataFormat>,
+    pub read_options: ReadOptions,
+    /// Adds a column with the path of the file each row comes from
+    pub filename_column: Option<String>,
+}
+
+impl FileSource {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            format: None,
+            read_options: ReadOptions::default(),
+            filename_column: None,
+        }
+    }
+
+    /// The files matching the pattern, sorted by path
+    pub fn files(&self) -> Result<Vec<PathBuf>, LakeApi2SqlError> {
+        let mut files = glob::glob(&self.pattern)
+            .map_err(|e| LakeApi2SqlError::InvalidOptions(format!("{}: {e}", self.pattern)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| LakeApi2SqlError::IOError(e.into_error()))?;
+        files.retain(|f| f.is_file());
+        if files.is_empty() {
+            return Err(LakeApi2SqlError::InvalidOptions(format!(
+                "no files match {}",
+                self.pattern
+            )));
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    fn format_of(&self, path: &Path) -> Result<DataFormat, LakeApi2SqlError> {
+        self.format
+            .or_else(|| DataFormat::from_path(path))
+            .ok_or_else(|| {
+                LakeApi2SqlError::InvalidOptions(format!(
+                    "cannot detect the format of {}",
+                    path.display()
+                ))
+            })
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn RecordBatchReader + Send>, LakeApi2SqlError> {
+        open_file(self.format_of(path)?, File::open(path)?, &self.read_options)
+    }
+}
+
+fn into_arrow_error(e: LakeApi2SqlError) -> ArrowError {
+    match e {
+        LakeApi2SqlError::ArrowError(e) => e,
+        LakeApi2SqlError::IOError(e) => ArrowError::IoError(e.to_string(), e),
+        e => ArrowError::ExternalError(e.to_string().into()),
+    }
+}
+
+/// Reads the files of a source one after the other
+pub struct FilesReader {
+    source: FileSource,
+    schema: SchemaRef,
+    /// Schema of the files, without the filename column
+    file_schema: SchemaRef,
+    files: VecDeque<PathBuf>,
+    current: Option<(Box<dyn RecordBatchReader + Send>, PathBuf)>,
+}
+
+impl FilesReader {
+    /// Opens the first file to get the schema
+    pub fn try_new(mut source: FileSource) -> Result<Self, LakeApi2SqlError> {
+        let mut files: VecDeque<PathBuf> = source.files()?.into();
+        let first = files.pop_front().expect("files is never empty");
+        let format = source.format_of(&first)?;
+        if matches!(format, DataFormat::Csv | DataFormat::NdJson)
+            && source.read_options.schema.is_none()
+        {
+            // inferring the schema of each file could give different types, eg for a column
+            // that is null in one of the files
+            let options = ReadOptions {
+                columns: None,
+                ..source.read_options.clone()
+            };
+            source.read_options.schema =
+                Some(open_file(format, File::open(&first)?, &options)?.schema());
+        }
+        let reader = source.open(&first)?;
+        let file_schema = reader.schema();
+        let schema = match &source.filename_column {
+            Some(c) => {
+                let mut fields = file_schema.fields().to_vec();
+                fields.push(Arc::new(Field::new(c, DataType::Utf8, false)));
+                Arc::new(Schema::new_with_metadata(
+                    fields,
+                    file_schema.metadata().clone(),
+                ))
+            }
+            None => file_schema.clone(),
+        };
+        Ok(Self {
+            source,
+            schema,
+            file_schema,
+            files,
+            current: Some((reader, first)),
+        })
+    }
+
+    fn with_filename(&self, batch: RecordBatch, path: &Path) -> Result<RecordBatch, ArrowError> {
+        if self.source.filename_column.is_none() {
+            return Ok(batch);
+        }
+        let mut columns = batch.columns().to_vec();
+        let name = path.to_string_lossy();
+        columns
+            .push(Arc::new(StringArray::from(vec![name.as_ref(); batch.num_rows()])) as ArrayRef);
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+
+    fn open_next(&mut self) -> Option<Result<(), ArrowError>> {
+        let path = self.files.pop_front()?;
+        let reader = match self.source.open(&path) {
+            Ok(r) => r,
+            Err(e) => return Some(Err(into_arrow_error(e))),
+        };
+        if reader.schema().fields() != self.file_schema.fields() {
+            return Some(Err(ArrowError::SchemaError(format!(
+                "{} has schema {:?}, expected {:?}",
+                path.display(),
+                reader.schema(),
+                self.file_schema
+            ))));
+        }
+        self.current = Some((reader, path));
+        Some(Ok(()))
+    }
+}
+
+impl Iterator for FilesReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((mut reader, path)) = self.current.take() {
+                match reader.next() {
+                    Some(Ok(batch)) => {
+                        let res = self.with_filename(batch, &path);
+                        self.current = Some((reader, path));
+                        return Some(res);
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => {}
+                }
+            }
+            if let Err(e) = self.open_next()? {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl RecordBatchReader for FilesReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
@@ -0,0 +1,338 @@
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, ACCEPT_ENCODING, CONTENT_TYPE, LINK};
+use reqwest::{
+    Certificate, Client, Identity, Method, Proxy, RequestBuilder, Response, StatusCode, Url,
+};
+use tokio::sync::Mutex;
+
+use crate::error::LakeApi2SqlError;
+use crate::source_format::DataFormat;
+
+/// OAuth2 client credentials flow, the token is fetched before the first request and refreshed once it
+/// expires
+#[derive(Clone)]
+pub struct OAuth2ClientCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum HttpAuth {
+    None,
+    Basic {
+        user: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    /// API key sent as query string parameter
+    ApiKeyQuery {
+        param: String,
+        key: String,
+    },
+    OAuth2(OAuth2ClientCredentials),
+}
+
+/// Client certificate for mTLS, both in PEM format
+#[derive(Clone)]
+pub struct ClientCertificate {
+    pub cert_pem: Vec<u8>,
+    pub key_pem: Vec<u8>,
+}
+
+impl ClientCertificate {
+    #[cfg(target_os = "linux")]
+    fn identity(&self) -> Result<Identity, reqwest::Error> {
+        Identity::from_pem(&[self.cert_pem.as_slice(), b"\n", self.key_pem.as_slice()].concat())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn identity(&self) -> Result<Identity, reqwest::Error> {
+        Identity::from_pkcs8_pem(&self.cert_pem, &self.key_pem)
+    }
+}
+
+/// How to get the next page of a paginated endpoint. All pages are loaded into the target in one load
+/// and must have the same schema
+#[derive(Debug, Clone)]
+pub enum Pagination {
+    /// The url of the next page is in the `Link` header with `rel="next"`, or in the given header.
+    /// The next url is requested as is, without the query parameters of the first request
+    NextLink { header: Option<String> },
+    /// The given response header holds the cursor of the next page, which is passed as query
+    /// parameter. The last page has no such header
+    Cursor { header: String, param: String },
+    /// The offset parameter is increased by the rows of each page, the limit parameter is set to
+    /// `page_size`. A page with less than `page_size` rows is the last one
+    Offset {
+        offset_param: String,
+        limit_param: Option<String>,
+        page_size: u64,
+    },
+}
+
+/// A page to request, the first one has no url and no query
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Page {
+    /// Replaces the url of the request
+    pub(crate) url: Option<String>,
+    pub(crate) query: Vec<(String, String)>,
+    offset: u64,
+}
+
+impl Pagination {
+    pub(crate) fn first_page(&self) -> Page {
+        match self {
+            Pagination::Offset {
+                offset_param,
+                limit_param,
+                page_size,
+            } => Page {
+                query: Self::offset_query(offset_param, limit_param, *page_size, 0),
+                ..Default::default()
+            },
+            _ => Page::default(),
+        }
+    }
+
+    /// The page following `page`, None if `page` was the last one
+    pub(crate) fn next_page(
+        &self,
+        page: &Page,
+        url: &Url,
+        headers: &HeaderMap,
+        rows: u64,
+    ) -> Option<Page> {
+        match self {
+            Pagination::NextLink { header } => {
+                let next = match header {
+                    Some(h) => headers.get(h)?.to_str().ok()?.trim().to_owned(),
+                    None => headers
+                        .get_all(LINK)
+                        .iter()
+                        .filter_map(|v| v.to_str().ok())
+                        .find_map(next_link)?,
+                };
+                Some(Page {
+                    url: Some(url.join(&next).ok()?.to_string()),
+                    ..Default::default()
+                })
+            }
+            Pagination::Cursor { header, param } => {
+                let cursor = headers.get(header)?.to_str().ok()?;
+                if cursor.is_empty() {
+                    return None;
+                }
+                Some(Page {
+                    query: vec![(param.clone(), cursor.to_owned())],
+                    ..Default::default()
+                })
+            }
+            Pagination::Offset {
+                offset_param,
+                limit_param,
+                page_size,
+            } => {
+                if rows == 0 || rows < *page_size {
+                    return None;
+                }
+                let offset = page.offset + rows;
+                Some(Page {
+                    url: None,
+                    query: Self::offset_query(offset_param, limit_param, *page_size, offset),
+                    offset,
+                })
+            }
+        }
+    }
+
+    fn offset_query(
+        offset_param: &str,
+        limit_param: &Option<String>,
+        page_size: u64,
+        offset: u64,
+    ) -> Vec<(String, String)> {
+        let mut query = vec![(offset_param.to_owned(), offset.to_string())];
+        if let Some(l) = limit_param {
+            query.push((l.clone(), page_size.to_string()));
+        }
+        query
+    }
+}
+
+/// Gets the `rel="next"` url out of a `Link` header like `<https://x/?page=2>; rel="next"`
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.trim().strip_prefix('<')?.split_once('>')?;
+        params
+            .split(';')
+            .filter_map(|p| p.trim().strip_prefix("rel="))
+            .any(|rel| rel.trim_matches('"').split(' ').any(|r| r == "next"))
+            .then(|| url.to_owned())
+    })
+}
+
+/// The http endpoint returning the data
+#[derive(Clone)]
+pub struct HttpRequest {
+    pub url: String,
+    pub method: Method,
+    pub auth: HttpAuth,
+    pub query: Vec<(String, String)>,
+    /// Additional headers, eg `Accept` or api keys in a header
+    pub headers: Vec<(String, String)>,
+    /// Sent as body with content type `application/json`, eg filters for POST endpoints
+    pub json_body: Option<serde_json::Value>,
+    /// Proxy url for http and https
+    pub proxy: Option<String>,
+    /// Additional root certificate in PEM format, eg for an internal CA
+    pub ca_certificate: Option<Vec<u8>>,
+    pub verify_tls: bool,
+    pub client_certificate: Option<ClientCertificate>,
+    /// Format of the response, detected by its `Content-Type` if None
+    pub format: Option<DataFormat>,
+    pub pagination: Option<Pagination>,
+}
+
+impl HttpRequest {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            method: Method::GET,
+            auth: HttpAuth::None,
+            query: vec![],
+            headers: vec![],
+            json_body: None,
+            proxy: None,
+            ca_certificate: None,
+            verify_tls: true,
+            client_certificate: None,
+            format: None,
+            pagination: None,
+        }
+    }
+
+    pub fn with_basic_auth(url: &str, user: &str, password: &str) -> Self {
+        Self {
+            auth: HttpAuth::Basic {
+                user: user.to_owned(),
+                password: Some(password.to_owned()),
+            },
+            ..Self::new(url)
+        }
+    }
+
+    pub(crate) fn client(&self) -> Result<Client, LakeApi2SqlError> {
+        let mut builder = Client::builder().danger_accept_invalid_certs(!self.verify_tls);
+        if let Some(cert) = &self.client_certificate {
+            builder = builder.identity(cert.identity()?);
+        }
+        if let Some(ca) = &self.ca_certificate {
+            builder = builder.add_root_certificate(Certificate::from_pem(ca)?);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Builds the request including authentication, a token is fetched if needed
+    pub(crate) async fn build(
+        &self,
+        client: &Client,
+        tokens: &TokenCache,
+    ) -> Result<RequestBuilder, LakeApi2SqlError> {
+        let mut req = client.request(self.method.clone(), &self.url);
+        if !self.query.is_empty() {
+            req = req.query(&self.query);
+        }
+        // reqwest only announces the encodings it decodes itself, zstd is decoded by the reader
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(ACCEPT_ENCODING.as_str()))
+        {
+            req = req.header(ACCEPT_ENCODING, "gzip, br, deflate, zstd");
+        }
+        for (name, value) in self.headers.iter() {
+            req = req.header(name, value);
+        }
+        if let Some(body) = &self.json_body {
+            req = req
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+        Ok(match &self.auth {
+            HttpAuth::None => req,
+            HttpAuth::Basic { user, password } => req.basic_auth(user, password.as_ref()),
+            HttpAuth::Bearer(token) => req.bearer_auth(token),
+            HttpAuth::ApiKeyQuery { param, key } => req.query(&[(param, key)]),
+            HttpAuth::OAuth2(creds) => req.bearer_auth(tokens.get(client, creds).await?),
+        })
+    }
+}
+
+/// Like `error_for_status`, but a 401 or 403 becomes an [`LakeApi2SqlError::AuthError`]
+pub(crate) fn check_status(res: Response) -> Result<Response, LakeApi2SqlError> {
+    match res.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            // the query may hold an api key
+            let mut url = res.url().clone();
+            url.set_query(None);
+            Err(LakeApi2SqlError::AuthError(format!(
+                "{url} returned {}",
+                res.status()
+            )))
+        }
+        _ => Ok(res.error_for_status()?),
+    }
+}
+
+/// Caches an OAuth2 token until shortly before it expires
+#[derive(Default)]
+pub(crate) struct TokenCache {
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenCache {
+    /// Tokens are refreshed this long before they expire
+    const MARGIN: Duration = Duration::from_secs(60);
+
+    pub(crate) async fn get(
+        &self,
+        client: &Client,
+        creds: &OAuth2ClientCredentials,
+    ) -> Result<String, LakeApi2SqlError> {
+        let mut token = self.token.lock().await;
+        if let Some((t, valid_until)) = token.as_ref() {
+            if Instant::now() < *valid_until {
+                return Ok(t.clone());
+            }
+        }
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", creds.client_id.as_str()),
+            ("client_secret", creds.client_secret.as_str()),
+        ];
+        if let Some(scope) = &creds.scope {
+            form.push(("scope", scope.as_str()));
+        }
+        let res = check_status(client.post(&creds.token_url).form(&form).send().await?)?
+            .bytes()
+            .await?;
+        let res: serde_json::Value = serde_json::from_slice(&res)
+            .map_err(|e| LakeApi2SqlError::AuthError(format!("invalid token response: {e}")))?;
+        let access_token = res["access_token"]
+            .as_str()
+            .ok_or_else(|| LakeApi2SqlError::AuthError("no access_token in response".to_owned()))?
+            .to_owned();
+        let expires_in = Duration::from_secs(res["expires_in"].as_u64().unwrap_or(3600));
+        *token = Some((
+            access_token.clone(),
+            Instant::now() + expires_in.saturating_sub(Self::MARGIN),
+        ));
+        Ok(access_token)
+    }
+}
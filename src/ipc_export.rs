@@ -0,0 +1,247 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use arrow::ipc::writer::{FileWriter, IpcWriteOptions, StreamWriter};
+use arrow::ipc::CompressionType;
+use arrow::record_batch::{RecordBatch, RecordBatchWriter};
+use futures::SinkExt;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Body;
+use tiberius::{Client, ToSql};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::Compat;
+
+use crate::error::LakeApi2SqlError;
+use crate::export::{export_query, ExportResult, Written};
+use crate::http::{HttpRequest, TokenCache};
+use crate::source_format::DataFormat;
+
+/// Size of the chunks of a http request body
+const BODY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Where an Arrow IPC export is written to
+#[derive(Clone)]
+pub enum IpcTarget {
+    File(PathBuf),
+    Stdout,
+    /// Sent as body of the request, which should be a PUT or POST
+    Http(HttpRequest),
+}
+
+/// How to write Arrow IPC data
+#[derive(Debug, Clone)]
+pub struct IpcExportOptions {
+    /// `ArrowStream` or `ArrowFile`
+    pub format: DataFormat,
+    /// Compression of the buffers, needs the `ipc_compression` feature of arrow
+    pub compression: Option<CompressionType>,
+}
+
+impl Default for IpcExportOptions {
+    fn default() -> Self {
+        Self {
+            format: DataFormat::ArrowStream,
+            compression: None,
+        }
+    }
+}
+
+/// Parses a compression name, one of none, lz4 or zstd
+pub fn ipc_compression_from_name(name: &str) -> Result<Option<CompressionType>, LakeApi2SqlError> {
+    match name.to_lowercase().as_str() {
+        "none" | "uncompressed" => Ok(None),
+        "lz4" | "lz4_frame" => Ok(Some(CompressionType::LZ4_FRAME)),
+        "zstd" => Ok(Some(CompressionType::ZSTD)),
+        n => Err(LakeApi2SqlError::InvalidOptions(format!(
+            "unknown compression {n}, use none, lz4 or zstd"
+        ))),
+    }
+}
+
+/// Counts the bytes written
+struct Counting<W: Write> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Sends the written bytes in chunks to the body of a http request
+struct BodyWriter {
+    tx: futures::channel::mpsc::Sender<Result<Vec<u8>, io::Error>>,
+    buf: Vec<u8>,
+}
+
+impl BodyWriter {
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(BODY_CHUNK_SIZE));
+        futures::executor::block_on(self.tx.send(Ok(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the http request was closed"))
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= BODY_CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.send()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_batches<W: RecordBatchWriter>(
+    writer: &mut W,
+    first: &RecordBatch,
+    rx: &mut mpsc::Receiver<RecordBatch>,
+) -> Result<(), LakeApi2SqlError> {
+    writer.write(first)?;
+    while let Some(batch) = rx.blocking_recv() {
+        writer.write(&batch)?;
+    }
+    Ok(())
+}
+
+/// Writes all batches of the channel in the IPC format, returns the number of bytes written
+pub fn write_ipc<W: Write>(
+    out: W,
+    options: &IpcExportOptions,
+    mut rx: mpsc::Receiver<RecordBatch>,
+) -> Result<u64, LakeApi2SqlError> {
+    // the query failed before it returned anything
+    let Some(first) = rx.blocking_recv() else {
+        return Ok(0);
+    };
+    let write_options = IpcWriteOptions::default().try_with_compression(options.compression)?;
+    let out = Counting {
+        inner: out,
+        bytes: 0,
+    };
+    let mut out = match options.format {
+        DataFormat::ArrowStream => {
+            let mut writer =
+                StreamWriter::try_new_with_options(out, &first.schema(), write_options)?;
+            write_batches(&mut writer, &first, &mut rx)?;
+            writer.into_inner()?
+        }
+        DataFormat::ArrowFile => {
+            let mut writer = FileWriter::try_new_with_options(out, &first.schema(), write_options)?;
+            write_batches(&mut writer, &first, &mut rx)?;
+            writer.into_inner()?
+        }
+        f => {
+            return Err(LakeApi2SqlError::InvalidOptions(format!(
+                "{f:?} is not an Arrow IPC format"
+            )))
+        }
+    };
+    out.flush()?;
+    Ok(out.bytes)
+}
+
+/// Runs the query and writes the result as Arrow IPC stream or file to the target
+pub async fn export_query_ipc(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    batch_rows: usize,
+    target: &IpcTarget,
+    options: IpcExportOptions,
+) -> Result<ExportResult, LakeApi2SqlError> {
+    match target {
+        IpcTarget::File(path) => {
+            let path = path.clone();
+            export_query(client, query, params, batch_rows, move |rx| {
+                let bytes = write_ipc(BufWriter::new(File::create(&path)?), &options, rx)?;
+                Ok(Written {
+                    files: vec![path],
+                    bytes,
+                })
+            })
+            .await
+        }
+        IpcTarget::Stdout => {
+            export_query(client, query, params, batch_rows, move |rx| {
+                Ok(Written {
+                    files: vec![],
+                    bytes: write_ipc(BufWriter::new(io::stdout()), &options, rx)?,
+                })
+            })
+            .await
+        }
+        IpcTarget::Http(request) => {
+            upload(client, query, params, batch_rows, request, options).await
+        }
+    }
+}
+
+/// Streams the result as body of a http request. If the query fails, the body is aborted so the
+/// server does not get a truncated but valid stream
+async fn upload(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    batch_rows: usize,
+    request: &HttpRequest,
+    options: IpcExportOptions,
+) -> Result<ExportResult, LakeApi2SqlError> {
+    let http = request.client()?;
+    let mut req = request.build(&http, &TokenCache::default()).await?;
+    if !request
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case(CONTENT_TYPE.as_str()))
+    {
+        req = req.header(CONTENT_TYPE, options.format.content_type());
+    }
+    let (body_tx, body_rx) = futures::channel::mpsc::channel(4);
+    let mut abort = body_tx.clone();
+    let send = req.body(Body::wrap_stream(body_rx)).send();
+    let export = async move {
+        let res = export_query(client, query, params, batch_rows, move |rx| {
+            let mut out = BodyWriter {
+                tx: body_tx,
+                buf: Vec::with_capacity(BODY_CHUNK_SIZE),
+            };
+            let bytes = write_ipc(&mut out, &options, rx)?;
+            out.flush()?;
+            Ok(Written {
+                files: vec![],
+                bytes,
+            })
+        })
+        .await;
+        if res.is_err() {
+            // the request might be gone already
+            let _ = abort.send(Err(io::Error::other("the export failed"))).await;
+        }
+        res
+    };
+    let (res, response) = tokio::join!(export, send);
+    match (res, response.map(|r| r.error_for_status())) {
+        // the server rejecting the upload is the cause of a failing writer
+        (_, Ok(Err(e))) => Err(e.into()),
+        (Err(e), _) => Err(e),
+        (Ok(_), Err(e)) => Err(e.into()),
+        (Ok(res), Ok(Ok(_))) => Ok(res),
+    }
+}
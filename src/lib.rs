@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use arrow::datatypes::{Field, Schema};
@@ -6,15 +7,52 @@ use arrow::ffi_stream::ArrowArrayStreamReader;
 use arrow::pyarrow::FromPyArrow;
 
 use futures::TryStreamExt;
-use pyo3::exceptions::{PyConnectionError, PyIOError, PyTypeError};
+use pyo3::exceptions::{PyConnectionError, PyIOError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList, PyString, PyTuple};
-mod arrow_convert;
+pub mod arrow_convert;
 pub mod bulk_insert;
+pub mod checkpoint;
 pub mod connect;
+pub mod csv_export;
+pub mod delta;
+pub mod delta_export;
 pub mod error;
+pub mod export;
+pub mod file_source;
+pub mod http;
+pub mod ipc_export;
+pub mod load_result;
+pub mod parquet_export;
+pub mod progress;
+mod rebatch;
+pub mod retry;
+pub mod source_format;
+pub mod sql_copy;
+pub mod sql_to_arrow;
+pub mod timeout;
+pub mod watermark;
+use bulk_insert::BulkInsertOptions;
+use checkpoint::Checkpoint;
+use connect::ConnectInfo;
+use csv_export::{CsvEncoding, CsvExportOptions, Quoting};
+use delta::DeltaVersion;
+use delta_export::{DeltaExportOptions, SaveMode};
+use error::LakeApi2SqlError;
+use export::ExportResult;
+use file_source::FileSource;
+use http::{ClientCertificate, HttpAuth, HttpRequest, OAuth2ClientCredentials, Pagination};
+use ipc_export::{IpcExportOptions, IpcTarget};
+use load_result::LoadResult;
+use parquet_export::ParquetExportOptions;
+use progress::{Progress, ProgressCallback};
+use retry::RetryPolicy;
+use source_format::{DataFormat, ReadOptions};
+use std::time::Duration;
 use tiberius::{FromSql, QueryItem, ResultMetadata, Row, ToSql};
+use timeout::with_timeout;
 use tokio::net::TcpStream;
+use watermark::{Watermark, WatermarkResult};
 
 fn field_into_dict<'a>(py: Python<'a>, field: &'a Field) -> &'a PyDict {
     let d = PyDict::new(py);
@@ -42,7 +80,179 @@ fn into_dict(py: Python<'_>, schema: Arc<Schema>) -> &PyDict {
     d.set_item("metadata", metadata.unwrap()).unwrap();
     d
 }
-fn into_dict_result(py: Python<'_>, meta: Option<ResultMetadata>, rows: Vec<Row>) -> &PyDict {
+fn watermark_into_dict(py: Python<'_>, wm: WatermarkResult) -> &PyDict {
+    let d = PyDict::new(py);
+    d.set_item("column", wm.column).unwrap();
+    d.set_item("old", wm.old).unwrap();
+    d.set_item("new", wm.new).unwrap();
+    d
+}
+fn load_result_into_dict(py: Python<'_>, res: LoadResult) -> &PyDict {
+    let d = into_dict(py, res.schema);
+    d.set_item("rows_read", res.stats.rows_read).unwrap();
+    d.set_item("rows_skipped", res.stats.rows_skipped).unwrap();
+    d.set_item("rows_written", res.stats.rows_written).unwrap();
+    d.set_item("batches", res.stats.batches).unwrap();
+    d.set_item("bytes_received", res.stats.bytes_received)
+        .unwrap();
+    d.set_item(
+        "conversion_seconds",
+        res.stats.conversion_time.as_secs_f64(),
+    )
+    .unwrap();
+    d.set_item("network_seconds", res.stats.network_time.as_secs_f64())
+        .unwrap();
+    d.set_item("server_seconds", res.stats.server_time.as_secs_f64())
+        .unwrap();
+    let columns: Vec<&PyDict> = res
+        .columns
+        .iter()
+        .map(|c| {
+            let cd = PyDict::new(py);
+            cd.set_item("name", c.name.clone()).unwrap();
+            cd.set_item("column_type", format!("{0:?}", c.column_type))
+                .unwrap();
+            cd.set_item("arrow_type", c.arrow_type.as_ref().map(|t| t.to_string()))
+                .unwrap();
+            cd
+        })
+        .collect();
+    d.set_item("columns", columns).unwrap();
+    if let Some(wm) = res.watermark {
+        d.set_item("watermark", watermark_into_dict(py, wm))
+            .unwrap();
+    }
+    d
+}
+fn export_result_into_dict(py: Python<'_>, res: ExportResult) -> &PyDict {
+    let d = into_dict(py, res.schema);
+    d.set_item("rows", res.rows).unwrap();
+    let files: Vec<String> = res
+        .files
+        .iter()
+        .map(|f| f.to_string_lossy().into_owned())
+        .collect();
+    d.set_item("files", files).unwrap();
+    d.set_item("bytes", res.bytes).unwrap();
+    d
+}
+/// Python types of sql values that pyo3 can't convert by itself
+struct PyValueTypes<'py> {
+    datetime: &'py PyAny,
+    date: &'py PyAny,
+    time: &'py PyAny,
+    timezone: &'py PyAny,
+    timedelta: &'py PyAny,
+    decimal: &'py PyAny,
+    uuid: &'py PyAny,
+}
+
+impl<'py> PyValueTypes<'py> {
+    fn import(py: Python<'py>) -> PyResult<Self> {
+        let datetime = py.import("datetime")?;
+        Ok(Self {
+            datetime: datetime.getattr("datetime")?,
+            date: datetime.getattr("date")?,
+            time: datetime.getattr("time")?,
+            timezone: datetime.getattr("timezone")?,
+            timedelta: datetime.getattr("timedelta")?,
+            decimal: py.import("decimal")?.getattr("Decimal")?,
+            uuid: py.import("uuid")?.getattr("UUID")?,
+        })
+    }
+
+    /// Python datetimes have microseconds, the 100ns of datetime2 are cut
+    fn datetime(
+        &self,
+        dt: tiberius::time::time::PrimitiveDateTime,
+        tz: Option<&PyAny>,
+    ) -> PyResult<PyObject> {
+        Ok(self
+            .datetime
+            .call1((
+                dt.year(),
+                dt.month() as u8,
+                dt.day(),
+                dt.hour(),
+                dt.minute(),
+                dt.second(),
+                dt.microsecond(),
+                tz,
+            ))?
+            .into())
+    }
+}
+
+fn sql_value_into_py(
+    py: Python<'_>,
+    types: &PyValueTypes,
+    val: &tiberius::ColumnData<'static>,
+) -> PyResult<PyObject> {
+    use tiberius::time::time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+    let from_sql_err = |e: tiberius::error::Error| PyErr::from(LakeApi2SqlError::from(e));
+    Ok(match val {
+        tiberius::ColumnData::U8(o) => o.into_py(py),
+        tiberius::ColumnData::I16(o) => o.into_py(py),
+        tiberius::ColumnData::I32(o) => o.into_py(py),
+        tiberius::ColumnData::I64(o) => o.into_py(py),
+        tiberius::ColumnData::F32(o) => o.into_py(py),
+        tiberius::ColumnData::F64(o) => o.into_py(py),
+        tiberius::ColumnData::Bit(o) => o.into_py(py),
+        tiberius::ColumnData::String(o) => o.as_ref().map(|x| x.clone().into_owned()).into_py(py),
+        tiberius::ColumnData::Guid(o) => match o {
+            Some(u) => types.uuid.call1((u.to_string(),))?.into(),
+            None => py.None(),
+        },
+        tiberius::ColumnData::Binary(o) => o.as_ref().map(|x| x.clone().into_owned()).into_py(py),
+        // the string keeps the scale, 1.50 stays Decimal("1.50")
+        tiberius::ColumnData::Numeric(o) => match o {
+            Some(n) => types.decimal.call1((n.to_string(),))?.into(),
+            None => py.None(),
+        },
+        tiberius::ColumnData::Xml(o) => o.as_ref().map(|x| x.clone().to_string()).into_py(py),
+        tiberius::ColumnData::DateTime(_)
+        | tiberius::ColumnData::SmallDateTime(_)
+        | tiberius::ColumnData::DateTime2(_) => {
+            match PrimitiveDateTime::from_sql(val).map_err(from_sql_err)? {
+                Some(dt) => types.datetime(dt, None)?,
+                None => py.None(),
+            }
+        }
+        tiberius::ColumnData::Date(_) => match Date::from_sql(val).map_err(from_sql_err)? {
+            Some(d) => types
+                .date
+                .call1((d.year(), d.month() as u8, d.day()))?
+                .into(),
+            None => py.None(),
+        },
+        tiberius::ColumnData::Time(_) => match Time::from_sql(val).map_err(from_sql_err)? {
+            Some(t) => types
+                .time
+                .call1((t.hour(), t.minute(), t.second(), t.microsecond()))?
+                .into(),
+            None => py.None(),
+        },
+        // keeps the offset of the value, so the datetime is the same as in sql server
+        tiberius::ColumnData::DateTimeOffset(_) => {
+            match OffsetDateTime::from_sql(val).map_err(from_sql_err)? {
+                Some(dto) => {
+                    let offset = types.timedelta.call1((0, dto.offset().whole_seconds()))?;
+                    let tz = types.timezone.call1((offset,))?;
+                    types.datetime(PrimitiveDateTime::new(dto.date(), dto.time()), Some(tz))?
+                }
+                None => py.None(),
+            }
+        }
+    })
+}
+
+/// The result as dict with the columns and the rows, as tuples or as dicts by column name
+fn into_dict_result(
+    py: Python<'_>,
+    meta: Option<ResultMetadata>,
+    rows: Vec<Row>,
+    as_dict: bool,
+) -> PyResult<&PyDict> {
     let d = PyDict::new(py);
     if let Some(meta) = meta {
         let fields: Vec<&PyDict> = meta
@@ -60,148 +270,533 @@ fn into_dict_result(py: Python<'_>, meta: Option<ResultMetadata>, rows: Vec<Row>
 
         d.set_item("columns", fields).unwrap();
     }
-    let py_rows = PyList::new(
-        py,
-        rows.iter().map(|row| {
-            PyTuple::new(
-                py,
-                row.cells()
-                    .map(|(_c, val)| match val {
-                        tiberius::ColumnData::U8(o) => o.into_py(py),
-                        tiberius::ColumnData::I16(o) => o.into_py(py),
-                        tiberius::ColumnData::I32(o) => o.into_py(py),
-                        tiberius::ColumnData::I64(o) => o.into_py(py),
-                        tiberius::ColumnData::F32(o) => o.into_py(py),
-                        tiberius::ColumnData::F64(o) => o.into_py(py),
-                        tiberius::ColumnData::Bit(o) => o.into_py(py),
-                        tiberius::ColumnData::String(o) => {
-                            o.as_ref().map(|x| x.clone().into_owned()).into_py(py)
-                        }
-                        tiberius::ColumnData::Guid(o) => o.map(|x| x.to_string()).into_py(py),
-                        tiberius::ColumnData::Binary(o) => {
-                            o.as_ref().map(|x| x.clone().into_owned()).into_py(py)
-                        }
-                        tiberius::ColumnData::Numeric(o) => o.map(|x| x.to_string()).into_py(py),
-                        tiberius::ColumnData::Xml(o) => {
-                            o.as_ref().map(|x| x.clone().to_string()).into_py(py)
-                        }
-                        tiberius::ColumnData::DateTime(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::PrimitiveDateTime::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                        tiberius::ColumnData::SmallDateTime(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::PrimitiveDateTime::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                        tiberius::ColumnData::Time(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::Time::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                        tiberius::ColumnData::Date(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::Date::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                        tiberius::ColumnData::DateTime2(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::PrimitiveDateTime::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                        tiberius::ColumnData::DateTimeOffset(o) => o
-                            .map(|_x| {
-                                tiberius::time::time::PrimitiveDateTime::from_sql(val)
-                                    .unwrap()
-                                    .unwrap()
-                                    .to_string()
-                            })
-                            .into_py(py),
-                    })
-                    .collect::<Vec<PyObject>>(),
-            )
-        }),
-    );
+    let types = PyValueTypes::import(py)?;
+    let py_rows = PyList::empty(py);
+    for row in rows.iter() {
+        if as_dict {
+            let r = PyDict::new(py);
+            for (c, val) in row.cells() {
+                r.set_item(c.name(), sql_value_into_py(py, &types, val)?)?;
+            }
+            py_rows.append(r)?;
+        } else {
+            let values = row
+                .cells()
+                .map(|(_c, val)| sql_value_into_py(py, &types, val))
+                .collect::<PyResult<Vec<PyObject>>>()?;
+            py_rows.append(PyTuple::new(py, values))?;
+        }
+    }
     d.set_item("rows", py_rows).unwrap();
-    d
+    Ok(d)
+}
+
+fn get_item<'a, T: FromPyObject<'a>>(d: &'a PyDict, key: &str) -> PyResult<Option<T>> {
+    match d.get_item(key)? {
+        Some(v) if !v.is_none() => Ok(Some(v.extract()?)),
+        _ => Ok(None),
+    }
+}
+
+fn bulk_options_from_py(
+    connection_string: &str,
+    options: Option<&PyDict>,
+) -> PyResult<BulkInsertOptions> {
+    let mut res = BulkInsertOptions {
+        command_timeout: timeout::command_timeout(connection_string),
+        retry: RetryPolicy::from_con_str(connection_string),
+        ..Default::default()
+    };
+    if let Some(d) = options {
+        if let Some(v) = get_item(d, "check_constraints")? {
+            res.check_constraints = v;
+        }
+        if let Some(v) = get_item(d, "fire_triggers")? {
+            res.fire_triggers = v;
+        }
+        if let Some(v) = get_item(d, "keep_nulls")? {
+            res.keep_nulls = v;
+        }
+        if let Some(v) = get_item(d, "keep_identity")? {
+            res.keep_identity = v;
+        }
+        if let Some(v) = get_item(d, "table_lock")? {
+            res.table_lock = v;
+        }
+        if let Some(v) = get_item(d, "order_hints")? {
+            res.order_hints = v;
+        }
+        if let Some(v) = get_item(d, "batch_rows")? {
+            res.batch_rows = Some(v);
+        }
+        if let Some(v) = get_item(d, "parallelism")? {
+            res.parallelism = v;
+        }
+        if let Some(v) = get_item(d, "pipeline_depth")? {
+            res.pipeline_depth = v;
+        }
+        if let Some(v) = get_item(d, "conversion_threads")? {
+            res.conversion_threads = v;
+        }
+        if let Some(v) = get_item::<f64>(d, "command_timeout")? {
+            res.command_timeout = Some(Duration::from_secs_f64(v));
+        }
+        if let Some(v) = get_item::<f64>(d, "http_read_timeout")? {
+            res.http_read_timeout = Some(Duration::from_secs_f64(v));
+        }
+        if let Some(v) = get_item::<f64>(d, "load_timeout")? {
+            res.load_timeout = Some(Duration::from_secs_f64(v));
+        }
+        if let Some(v) = get_item(d, "max_retries")? {
+            res.retry.max_retries = v;
+        }
+        if let Some(v) = get_item::<f64>(d, "retry_backoff")? {
+            res.retry.initial_backoff = Duration::from_secs_f64(v);
+        }
+        if let Some(v) = get_item(d, "retry_error_codes")? {
+            res.retry.error_codes = v;
+        }
+    }
+    res.validate()?;
+    Ok(res)
+}
+
+/// Passes the progress as dict to a python callable
+struct PyProgressCallback(PyObject);
+
+impl ProgressCallback for PyProgressCallback {
+    fn on_progress(&self, progress: &Progress) {
+        Python::with_gil(|py| {
+            let d = PyDict::new(py);
+            d.set_item("rows_written", progress.rows_written).unwrap();
+            d.set_item("batches", progress.batches).unwrap();
+            d.set_item("bytes_received", progress.bytes_received)
+                .unwrap();
+            d.set_item("elapsed_seconds", progress.elapsed.as_secs_f64())
+                .unwrap();
+            if let Err(e) = self.0.call1(py, (d,)) {
+                log::warn!("Error in progress callback: {e}");
+            }
+        })
+    }
+}
+
+fn progress_from_py(callback: Option<PyObject>) -> Option<Arc<dyn ProgressCallback>> {
+    callback.map(|cb| Arc::new(PyProgressCallback(cb)) as Arc<dyn ProgressCallback>)
+}
+
+fn pagination_from_py(d: &PyDict) -> PyResult<Pagination> {
+    let kind: String = get_item(d, "type")?.unwrap_or_else(|| "next_link".to_owned());
+    let required = |key: &str| -> PyResult<String> {
+        get_item(d, key)?
+            .ok_or_else(|| PyErr::new::<PyValueError, _>(format!("{kind} pagination needs {key}")))
+    };
+    match kind.as_str() {
+        "next_link" => Ok(Pagination::NextLink {
+            header: get_item(d, "header")?,
+        }),
+        "cursor" => Ok(Pagination::Cursor {
+            header: required("header")?,
+            param: required("param")?,
+        }),
+        "offset" => Ok(Pagination::Offset {
+            offset_param: get_item(d, "param")?.unwrap_or_else(|| "offset".to_owned()),
+            limit_param: get_item(d, "limit_param")?,
+            page_size: get_item(d, "page_size")?.ok_or_else(|| {
+                PyErr::new::<PyValueError, _>("offset pagination needs page_size")
+            })?,
+        }),
+        _ => Err(PyErr::new::<PyValueError, _>(format!(
+            "Unknown pagination type: {kind}"
+        ))),
+    }
 }
 
+fn single_byte(d: &PyDict, key: &str) -> PyResult<Option<u8>> {
+    match get_item::<String>(d, key)? {
+        Some(v) if v.len() == 1 => Ok(Some(v.as_bytes()[0])),
+        Some(v) => Err(PyErr::new::<PyValueError, _>(format!(
+            "{key} must be a single byte character, not {v:?}"
+        ))),
+        None => Ok(None),
+    }
+}
+
+fn file_source_from_py(path: &str, options: Option<&PyDict>) -> PyResult<FileSource> {
+    let mut res = FileSource::new(path);
+    if let Some(d) = options {
+        if let Some(format) = get_item::<String>(d, "format")? {
+            res.format = Some(DataFormat::from_name(&format).ok_or_else(|| {
+                PyErr::new::<PyValueError, _>(format!("Unknown format: {format}"))
+            })?);
+        }
+        let mut read_options = ReadOptions {
+            columns: get_item(d, "columns")?,
+            ..Default::default()
+        };
+        if let Some(schema) = get_item::<&PyAny>(d, "schema")? {
+            read_options.schema = Some(Arc::new(Schema::from_pyarrow(schema)?));
+        }
+        if let Some(delimiter) = single_byte(d, "csv_delimiter")? {
+            read_options.csv_delimiter = delimiter;
+        }
+        if let Some(quote) = single_byte(d, "csv_quote")? {
+            read_options.csv_quote = quote;
+        }
+        if let Some(has_header) = get_item(d, "csv_has_header")? {
+            read_options.csv_has_header = has_header;
+        }
+        if let Some(batch_size) = get_item(d, "batch_size")? {
+            read_options.batch_size = batch_size;
+        }
+        res.read_options = read_options;
+        res.filename_column = get_item(d, "filename_column")?;
+    }
+    Ok(res)
+}
+
+/// Rows per batch read from the server, for all exports
+fn batch_rows_from_py(options: Option<&PyDict>) -> PyResult<usize> {
+    match options {
+        Some(d) => match get_item::<usize>(d, "batch_rows")? {
+            Some(0) => Err(PyErr::new::<PyValueError, _>("batch_rows must be > 0")),
+            Some(v) => Ok(v),
+            None => Ok(export::DEFAULT_BATCH_ROWS),
+        },
+        None => Ok(export::DEFAULT_BATCH_ROWS),
+    }
+}
+
+fn parquet_options_from_py(options: Option<&PyDict>) -> PyResult<ParquetExportOptions> {
+    let mut res = ParquetExportOptions::default();
+    let Some(d) = options else {
+        return Ok(res);
+    };
+    if let Some(v) = get_item::<usize>(d, "row_group_rows")? {
+        if v == 0 {
+            return Err(PyErr::new::<PyValueError, _>("row_group_rows must be > 0"));
+        }
+        res.row_group_rows = v;
+    }
+    if let Some(v) = get_item::<String>(d, "compression")? {
+        res.compression =
+            parquet_export::compression_from_name(&v, get_item(d, "compression_level")?)?;
+    }
+    res.max_rows_per_file = get_item(d, "max_rows_per_file")?;
+    if res.max_rows_per_file == Some(0) {
+        return Err(PyErr::new::<PyValueError, _>(
+            "max_rows_per_file must be > 0",
+        ));
+    }
+    res.max_bytes_per_file = get_item(d, "max_bytes_per_file")?;
+    Ok(res)
+}
+
+fn delta_options_from_py(options: Option<&PyDict>) -> PyResult<DeltaExportOptions> {
+    let mut res = DeltaExportOptions {
+        parquet: parquet_options_from_py(options)?,
+        ..Default::default()
+    };
+    let Some(d) = options else {
+        return Ok(res);
+    };
+    if let Some(mode) = get_item::<String>(d, "mode")? {
+        res.mode = SaveMode::from_name(&mode).ok_or_else(|| {
+            PyErr::new::<PyValueError, _>(format!("Unknown mode: {mode}, use append or overwrite"))
+        })?;
+    }
+    if let Some(columns) = get_item(d, "partition_by")? {
+        res.partition_columns = columns;
+    }
+    Ok(res)
+}
+
+fn csv_options_from_py(path: &str, options: Option<&PyDict>) -> PyResult<CsvExportOptions> {
+    let mut res = CsvExportOptions::default();
+    let Some(d) = options else {
+        return Ok(res);
+    };
+    if let Some(v) = get_item(d, "delimiter")? {
+        res.delimiter = v;
+    }
+    if let Some(v) = get_item(d, "row_terminator")? {
+        res.row_terminator = v;
+    }
+    if let Some(v) = get_item(d, "quote")? {
+        res.quote = v;
+    }
+    if let Some(v) = get_item::<String>(d, "quoting")? {
+        res.quoting = Quoting::from_name(&v).ok_or_else(|| {
+            PyErr::new::<PyValueError, _>(format!("Unknown quoting: {v}, use minimal, all or none"))
+        })?;
+    }
+    if let Some(v) = get_item(d, "null")? {
+        res.null_value = v;
+    }
+    if let Some(v) = get_item(d, "header")? {
+        res.header = v;
+    }
+    if let Some(v) = get_item(d, "date_format")? {
+        res.date_format = v;
+    }
+    if let Some(v) = get_item(d, "datetime_format")? {
+        res.datetime_format = v;
+    }
+    if let Some(v) = get_item(d, "datetimeoffset_format")? {
+        res.datetimeoffset_format = v;
+    }
+    if let Some(v) = get_item(d, "time_format")? {
+        res.time_format = v;
+    }
+    // a code page number or a name
+    if let Some(v) = get_item::<&PyAny>(d, "encoding")? {
+        let name = match v.extract::<u32>() {
+            Ok(cp) => cp.to_string(),
+            Err(_) => v.extract()?,
+        };
+        res.encoding = CsvEncoding::from_name(&name)?;
+    }
+    // true puts the format file next to the csv file
+    if let Some(v) = get_item::<&PyAny>(d, "format_file")? {
+        res.format_file = match v.extract::<bool>() {
+            Ok(true) => Some(std::path::Path::new(path).with_extension("fmt")),
+            Ok(false) => None,
+            Err(_) => Some(v.extract::<String>()?.into()),
+        };
+    }
+    res.validate()?;
+    Ok(res)
+}
+
+fn ipc_options_from_py(options: Option<&PyDict>) -> PyResult<IpcExportOptions> {
+    let mut res = IpcExportOptions::default();
+    let Some(d) = options else {
+        return Ok(res);
+    };
+    if let Some(format) = get_item::<String>(d, "format")? {
+        res.format = match DataFormat::from_name(&format) {
+            Some(f @ (DataFormat::ArrowStream | DataFormat::ArrowFile)) => f,
+            _ => {
+                return Err(PyErr::new::<PyValueError, _>(format!(
+                    "Unknown format: {format}, use arrow_stream or arrow_file"
+                )))
+            }
+        };
+    }
+    if let Some(v) = get_item::<String>(d, "compression")? {
+        res.compression = ipc_export::ipc_compression_from_name(&v)?;
+    }
+    Ok(res)
+}
+
+/// `-` is stdout, http(s) urls get the result as body of a PUT (or a POST if set as `method`)
+fn ipc_target_from_py(
+    target: String,
+    auth: Option<&PyDict>,
+    request: Option<&PyDict>,
+) -> PyResult<IpcTarget> {
+    if target == "-" {
+        return Ok(IpcTarget::Stdout);
+    }
+    if !(target.starts_with("http://") || target.starts_with("https://")) {
+        return Ok(IpcTarget::File(target.into()));
+    }
+    let mut res = http_request_from_py(target, None, None, auth, request)?;
+    if res.method == reqwest::Method::GET {
+        res.method = reqwest::Method::PUT;
+    }
+    Ok(IpcTarget::Http(res))
+}
+
+fn http_request_from_py(
+    url: String,
+    user: Option<String>,
+    password: Option<String>,
+    auth: Option<&PyDict>,
+    request: Option<&PyDict>,
+) -> PyResult<HttpRequest> {
+    let mut res = HttpRequest::new(&url);
+    if let Some(d) = request {
+        if let Some(method) = get_item::<String>(d, "method")? {
+            res.method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())
+                .map_err(|e| PyErr::new::<PyValueError, _>(format!("{e}")))?;
+        }
+        if let Some(params) = get_item::<HashMap<String, &PyAny>>(d, "params")? {
+            for (k, v) in params {
+                res.query.push((k, v.str()?.to_string()));
+            }
+        }
+        if let Some(headers) = get_item::<HashMap<String, String>>(d, "headers")? {
+            res.headers.extend(headers);
+        }
+        if let Some(body) = get_item::<&PyAny>(d, "json")? {
+            let body: String = d
+                .py()
+                .import("json")?
+                .call_method1("dumps", (body,))?
+                .extract()?;
+            res.json_body = Some(
+                serde_json::from_str(&body)
+                    .map_err(|e| PyErr::new::<PyValueError, _>(format!("{e}")))?,
+            );
+        }
+        res.proxy = get_item(d, "proxy")?;
+        if let Some(ca) = get_item::<String>(d, "ca_cert")? {
+            res.ca_certificate = Some(std::fs::read(ca)?);
+        }
+        if let Some(verify) = get_item(d, "verify_tls")? {
+            res.verify_tls = verify;
+        }
+        if let Some(p) = get_item::<&PyDict>(d, "pagination")? {
+            res.pagination = Some(pagination_from_py(p)?);
+        }
+        if let Some(format) = get_item::<String>(d, "format")? {
+            res.format = Some(DataFormat::from_name(&format).ok_or_else(|| {
+                PyErr::new::<PyValueError, _>(format!("Unknown format: {format}"))
+            })?);
+        }
+    }
+    if let Some(user) = user {
+        res.auth = HttpAuth::Basic { user, password };
+    }
+    if let Some(d) = auth {
+        if let Some(token) = get_item(d, "bearer_token")? {
+            res.auth = HttpAuth::Bearer(token);
+        }
+        if let Some(key) = get_item(d, "api_key")? {
+            res.auth = HttpAuth::ApiKeyQuery {
+                param: get_item(d, "api_key_param")?.unwrap_or_else(|| "api_key".to_owned()),
+                key,
+            };
+        }
+        if let Some(token_url) = get_item(d, "oauth2_token_url")? {
+            let required = |key: &str| -> PyResult<String> {
+                get_item(d, key)?.ok_or_else(|| {
+                    PyErr::new::<PyValueError, _>(format!(
+                        "{key} is required with oauth2_token_url"
+                    ))
+                })
+            };
+            res.auth = HttpAuth::OAuth2(OAuth2ClientCredentials {
+                token_url,
+                client_id: required("oauth2_client_id")?,
+                client_secret: required("oauth2_client_secret")?,
+                scope: get_item(d, "oauth2_scope")?,
+            });
+        }
+        if let Some(headers) = get_item::<HashMap<String, String>>(d, "headers")? {
+            res.headers.extend(headers);
+        }
+        if let Some(cert) = get_item::<String>(d, "client_cert")? {
+            // the key can be in the same file as the certificate
+            let key = get_item::<String>(d, "client_key")?.unwrap_or_else(|| cert.clone());
+            res.client_certificate = Some(ClientCertificate {
+                cert_pem: std::fs::read(cert)?,
+                key_pem: std::fs::read(key)?,
+            });
+        }
+    }
+    Ok(res)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn insert_arrow_stream_to_sql_rs(
     connection_string: String,
     table_name: String,
     column_names: Vec<String>,
-    url: String,
-    user: String,
-    password: String,
+    request: HttpRequest,
     aad_token: Option<String>,
-) -> Result<Arc<Schema>, PyErr> {
-    let db_client = connect::connect_sql(&connection_string, aad_token).await;
-    if let Err(er) = db_client {
-        return Err(PyErr::new::<PyConnectionError, _>(format!(
-            "Error connecting: {er}"
-        )));
-    }
-    let mut db_client = db_client.unwrap();
-    let bres = bulk_insert::bulk_insert(
-        &mut db_client,
-        &table_name,
-        &column_names
-            .iter()
-            .map(|x| x.as_str())
-            .collect::<Vec<&str>>(),
-        &url,
-        &user,
-        &password,
-    )
-    .await;
-    if let Err(er) = bres {
-        return Err(PyErr::new::<PyIOError, _>(format!(
-            "Error connecting: {er}"
-        )));
-    }
-    Ok(bres.unwrap())
+    options: BulkInsertOptions,
+    watermark: Option<Watermark>,
+) -> Result<LoadResult, PyErr> {
+    let db_clients = options.connect(&connection_string, aad_token).await;
+    let mut db_clients = match db_clients {
+        Ok(c) => c,
+        Err(er @ LakeApi2SqlError::Timeout(_)) => return Err(er.into()),
+        Err(er) => {
+            return Err(PyErr::new::<PyConnectionError, _>(format!(
+                "Error connecting: {er}"
+            )))
+        }
+    };
+    let column_names = column_names
+        .iter()
+        .map(|x| x.as_str())
+        .collect::<Vec<&str>>();
+    let bres = match watermark {
+        Some(wm) => {
+            bulk_insert::bulk_insert_incremental(
+                &mut db_clients,
+                &table_name,
+                &column_names,
+                &request,
+                &options,
+                &wm,
+            )
+            .await
+        }
+        None => {
+            bulk_insert::bulk_insert_request(
+                &mut db_clients,
+                &table_name,
+                &column_names,
+                &request,
+                &options,
+            )
+            .await
+        }
+    };
+    Ok(bres?)
 }
 
 #[pyfunction]
+#[allow(clippy::too_many_arguments)]
 fn insert_arrow_stream_to_sql(
     py: Python,
     connection_string: String,
     table_name: String,
     column_names: Vec<String>,
     url: String,
-    user: String,
-    password: String,
+    user: Option<String>,
+    password: Option<String>,
     aad_token: Option<String>,
+    bulk_options: Option<&PyDict>,
+    watermark_column: Option<String>,
+    watermark_param: Option<String>,
+    progress_callback: Option<PyObject>,
+    checkpoint_file: Option<String>,
+    checkpoint_skip_param: Option<String>,
+    http_auth: Option<&PyDict>,
+    http_request: Option<&PyDict>,
 ) -> PyResult<&PyAny> {
+    let request = http_request_from_py(url, user, password, http_auth, http_request)?;
+    let mut options = bulk_options_from_py(&connection_string, bulk_options)?;
+    options.progress = progress_from_py(progress_callback);
+    options.reconnect = Some(ConnectInfo {
+        connection_string: connection_string.clone(),
+        aad_token: aad_token.clone(),
+    });
+    options.checkpoint = checkpoint_file.map(|path| Checkpoint {
+        path: path.into(),
+        skip_param: checkpoint_skip_param,
+    });
+    let watermark = watermark_column.map(|column| Watermark {
+        query_param: watermark_param.unwrap_or_else(|| column.clone()),
+        column,
+    });
     pyo3_asyncio::tokio::future_into_py(py, async move {
         let res = insert_arrow_stream_to_sql_rs(
             connection_string,
             table_name,
             column_names,
-            url,
-            user,
-            password,
+            request,
             aad_token,
+            options,
+            watermark,
         )
         .await?;
         Ok(Python::with_gil(|py| {
-            let d: Py<PyDict> = into_dict(py, res).into();
+            let d: Py<PyDict> = load_result_into_dict(py, res).into();
             d
         }))
     })
@@ -209,9 +804,7 @@ fn insert_arrow_stream_to_sql(
 
 /// Opaque type to transport connection to an MsSqlConnection over language boundry
 #[pyclass]
-pub struct MsSqlConnection(
-    Arc<tokio::sync::Mutex<tiberius::Client<tokio_util::compat::Compat<TcpStream>>>>,
-);
+pub struct MsSqlConnection(Arc<tokio::sync::Mutex<connect::ReconnectingClient>>);
 
 #[pyfunction]
 fn connect_sql(
@@ -220,12 +813,13 @@ fn connect_sql(
     aad_token: Option<String>,
 ) -> PyResult<&PyAny> {
     pyo3_asyncio::tokio::future_into_py(py, async move {
-        let res = connect::connect_sql(&connection_string, aad_token).await;
+        let res = connect::ReconnectingClient::connect(&connection_string, aad_token).await;
 
         match res {
             Ok(re) => Python::with_gil(|py| {
                 Py::new(py, MsSqlConnection(Arc::new(tokio::sync::Mutex::new(re))))
             }),
+            Err(er @ LakeApi2SqlError::Timeout(_)) => Err(er.into()),
             Err(er) => Err(PyErr::new::<PyConnectionError, _>(format!(
                 "Error connecting: {er}"
             ))),
@@ -284,12 +878,12 @@ fn execute_sql<'a>(
 
     let mutex = conn.0.clone();
     pyo3_asyncio::tokio::future_into_py(py, async move {
-        let res = if nr_args > 0 {
-            mutex
-                .clone()
-                .lock()
-                .await
-                .execute(
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        // if this future gets cancelled or times out, conn is dropped and the next query reconnects
+        let res = with_timeout(rcon.command_timeout(), "query", async {
+            let res = if nr_args > 0 {
+                conn.execute(
                     query,
                     tds_args
                         .iter()
@@ -299,96 +893,287 @@ fn execute_sql<'a>(
                 )
                 .await
                 .map(|x| x.rows_affected().to_owned())
-        } else {
-            let arc = mutex.clone();
-            let lock = arc.lock();
-            let mut conn = lock.await;
-            let res = conn.simple_query(query).await;
-            match res {
-                Ok(mut stream) => {
-                    let mut row_count: u64 = 0;
-                    while let Some(item) = stream.try_next().await.map_err(|er| {
-                        PyErr::new::<PyIOError, _>(format!("Error executing: {er}"))
-                    })? {
-                        if let QueryItem::Row(_) = item {
-                            row_count += 1;
+            } else {
+                match conn.simple_query(query).await {
+                    Ok(mut stream) => {
+                        let mut row_count: u64 = 0;
+                        let mut res = Ok(());
+                        loop {
+                            match stream.try_next().await {
+                                Ok(Some(QueryItem::Row(_))) => row_count += 1,
+                                Ok(Some(_)) => {}
+                                Ok(None) => break,
+                                Err(er) => {
+                                    res = Err(er);
+                                    break;
+                                }
+                            }
                         }
+                        res.map(|_| vec![row_count])
                     }
-                    Ok(vec![row_count])
+                    Err(a) => Err(a),
                 }
-                Err(a) => Err(a),
-            }
-        };
+            };
+            res.map_err(LakeApi2SqlError::from)
+        })
+        .await;
+        rcon.give_back_if_done(conn, &res);
 
         match res {
             Ok(re) => Ok(into_list(&re)),
-            Err(er) => Err(PyErr::new::<PyIOError, _>(format!("Error executing: {er}"))),
+            Err(er) => Err(query_error(er)),
         }
     })
 }
 
+/// Timeouts become a TimeoutError, everything else an IOError
+fn query_error(er: LakeApi2SqlError) -> PyErr {
+    match er {
+        LakeApi2SqlError::Timeout(_) => er.into(),
+        er => PyErr::new::<PyIOError, _>(format!("Error executing: {er}")),
+    }
+}
+
+async fn query_rows(
+    conn: &mut tiberius::Client<tokio_util::compat::Compat<TcpStream>>,
+    query: String,
+    tds_args: Vec<ValueWrap>,
+) -> Result<(Option<ResultMetadata>, Vec<Row>), tiberius::error::Error> {
+    let mut stream = conn
+        .query(
+            query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+        )
+        .await?;
+    let mut meta = None;
+    let mut rows = vec![];
+    while let Some(item) = stream.try_next().await? {
+        match item {
+            // our first item is the column data always
+            QueryItem::Metadata(m) if m.result_index() == 0 => {
+                meta = Some(m);
+                // the first result column info can be handled here
+            }
+            // ... and from there on from 0..N rows
+            QueryItem::Row(row) if row.result_index() == 0 => rows.push(row),
+            // the other result sets are read to the end as well, so the connection can run the next
+            // query, but not returned
+            QueryItem::Metadata(_) | QueryItem::Row(_) => {}
+        }
+    }
+    Ok((meta, rows))
+}
+
 #[pyfunction]
 fn execute_sql_with_result<'a>(
     py: Python<'a>,
     conn: &MsSqlConnection,
     query: String,
     args: Vec<&PyAny>,
+    as_dict: bool,
 ) -> PyResult<&'a PyAny> {
     let tds_args = to_exec_args(args)?;
 
     let mutex = conn.0.clone();
     pyo3_asyncio::tokio::future_into_py(py, async move {
-        let arc = mutex.clone();
-        let mut conn = arc.lock().await;
-        let res = conn
-            .query(
-                query,
-                tds_args
-                    .iter()
-                    .map(|x| x.0.borrow() as &dyn ToSql)
-                    .collect::<Vec<&dyn ToSql>>()
-                    .as_slice(),
-            )
-            .await;
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        // if this future gets cancelled or times out, conn is dropped and the next query reconnects
+        let res = with_timeout(rcon.command_timeout(), "query", async {
+            Ok(query_rows(&mut conn, query, tds_args).await?)
+        })
+        .await;
+        rcon.give_back_if_done(conn, &res);
 
         match res {
-            Ok(mut stream) => {
-                let mut meta = None;
-                let mut rows = vec![];
-                while let Some(item) = stream
-                    .try_next()
-                    .await
-                    .map_err(|er| PyErr::new::<PyIOError, _>(format!("Error executing: {er}")))?
-                {
-                    match item {
-                        // our first item is the column data always
-                        QueryItem::Metadata(m) if m.result_index() == 0 => {
-                            meta = Some(m);
-                            // the first result column info can be handled here
-                        }
-                        // ... and from there on from 0..N rows
-                        QueryItem::Row(row) if row.result_index() == 0 => rows.push(row),
-                        // the second result set returns first another metadata item
-                        QueryItem::Metadata(_meta) => {
-                            break;
-                        }
-                        // ...and, again, we get rows from the second resultset
-                        QueryItem::Row(_row) => {
-                            break;
-                        }
-                    }
-                }
-                Ok(Python::with_gil(|py| {
-                    let d: Py<PyDict> = into_dict_result(py, meta, rows).into();
-                    d
-                }))
-            }
-            Err(er) => Err(PyErr::new::<PyIOError, _>(format!("Error executing: {er}"))),
+            Ok((meta, rows)) => Python::with_gil(|py| {
+                let d: Py<PyDict> = into_dict_result(py, meta, rows, as_dict)?.into();
+                Ok(d)
+            }),
+            Err(er) => Err(query_error(er)),
+        }
+    })
+}
+
+#[pyfunction]
+fn export_query_to_parquet<'a>(
+    py: Python<'a>,
+    conn: &MsSqlConnection,
+    query: String,
+    args: Vec<&PyAny>,
+    path: String,
+    options: Option<&PyDict>,
+) -> PyResult<&'a PyAny> {
+    let tds_args = to_exec_args(args)?;
+    let batch_rows = batch_rows_from_py(options)?;
+    let parquet_options = parquet_options_from_py(options)?;
+
+    let mutex = conn.0.clone();
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        // no command timeout, an export takes as long as the result is large
+        let res = export::export_query(
+            &mut conn,
+            &query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+            batch_rows,
+            move |rx| {
+                parquet_export::write_parquet(std::path::Path::new(&path), parquet_options, rx)
+            },
+        )
+        .await;
+        rcon.give_back_if_done(conn, &res);
+
+        match res {
+            Ok(r) => Ok(Python::with_gil(|py| {
+                let d: Py<PyDict> = export_result_into_dict(py, r).into();
+                d
+            })),
+            Err(er) => Err(query_error(er)),
+        }
+    })
+}
+
+#[pyfunction]
+fn export_query_to_csv<'a>(
+    py: Python<'a>,
+    conn: &MsSqlConnection,
+    query: String,
+    args: Vec<&PyAny>,
+    path: String,
+    options: Option<&PyDict>,
+) -> PyResult<&'a PyAny> {
+    let tds_args = to_exec_args(args)?;
+    let batch_rows = batch_rows_from_py(options)?;
+    let csv_options = csv_options_from_py(&path, options)?;
+
+    let mutex = conn.0.clone();
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        let res = export::export_query(
+            &mut conn,
+            &query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+            batch_rows,
+            move |rx| csv_export::write_csv(std::path::Path::new(&path), csv_options, rx),
+        )
+        .await;
+        rcon.give_back_if_done(conn, &res);
+
+        match res {
+            Ok(r) => Ok(Python::with_gil(|py| {
+                let d: Py<PyDict> = export_result_into_dict(py, r).into();
+                d
+            })),
+            Err(er) => Err(query_error(er)),
+        }
+    })
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn export_query_to_arrow<'a>(
+    py: Python<'a>,
+    conn: &MsSqlConnection,
+    query: String,
+    args: Vec<&PyAny>,
+    target: String,
+    options: Option<&PyDict>,
+    http_auth: Option<&PyDict>,
+    http_request: Option<&PyDict>,
+) -> PyResult<&'a PyAny> {
+    let tds_args = to_exec_args(args)?;
+    let batch_rows = batch_rows_from_py(options)?;
+    let ipc_options = ipc_options_from_py(options)?;
+    let target = ipc_target_from_py(target, http_auth, http_request)?;
+
+    let mutex = conn.0.clone();
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        let res = ipc_export::export_query_ipc(
+            &mut conn,
+            &query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+            batch_rows,
+            &target,
+            ipc_options,
+        )
+        .await;
+        rcon.give_back_if_done(conn, &res);
+
+        match res {
+            Ok(r) => Ok(Python::with_gil(|py| {
+                let d: Py<PyDict> = export_result_into_dict(py, r).into();
+                d
+            })),
+            Err(er) => Err(query_error(er)),
+        }
+    })
+}
+
+#[pyfunction]
+fn export_query_to_delta<'a>(
+    py: Python<'a>,
+    conn: &MsSqlConnection,
+    query: String,
+    args: Vec<&PyAny>,
+    table_path: String,
+    options: Option<&PyDict>,
+) -> PyResult<&'a PyAny> {
+    let tds_args = to_exec_args(args)?;
+    let batch_rows = batch_rows_from_py(options)?;
+    let delta_options = delta_options_from_py(options)?;
+
+    let mutex = conn.0.clone();
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        let res = delta_export::export_query_delta(
+            &mut conn,
+            &query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+            batch_rows,
+            std::path::Path::new(&table_path),
+            delta_options,
+        )
+        .await;
+        rcon.give_back_if_done(conn, &res);
+
+        match res {
+            Ok(r) => Ok(Python::with_gil(|py| {
+                let d: Py<PyDict> = export_result_into_dict(py, r).into();
+                d
+            })),
+            Err(er) => Err(query_error(er)),
         }
     })
 }
 
 #[pyfunction]
+#[allow(clippy::too_many_arguments)]
 fn insert_arrow_reader_to_sql<'a>(
     py: Python<'a>,
     connection_string: String,
@@ -396,25 +1181,184 @@ fn insert_arrow_reader_to_sql<'a>(
     table_name: String,
     column_names: Vec<String>,
     aad_token: Option<String>,
+    bulk_options: Option<&PyDict>,
+    progress_callback: Option<PyObject>,
 ) -> PyResult<&'a PyAny> {
-    let mut reader: ArrowArrayStreamReader =
-        ArrowArrayStreamReader::from_pyarrow(record_batch_reader)?;
+    let reader: ArrowArrayStreamReader = ArrowArrayStreamReader::from_pyarrow(record_batch_reader)?;
+    let mut options = bulk_options_from_py(&connection_string, bulk_options)?;
+    options.progress = progress_from_py(progress_callback);
+    options.reconnect = Some(ConnectInfo {
+        connection_string: connection_string.clone(),
+        aad_token: aad_token.clone(),
+    });
 
     pyo3_asyncio::tokio::future_into_py(py, async move {
-        let mut db_client = connect::connect_sql(&connection_string, aad_token).await?;
+        let mut db_clients = options.connect(&connection_string, aad_token).await?;
         let bres = bulk_insert::bulk_insert_reader(
-            &mut db_client,
+            &mut db_clients,
+            &table_name,
+            &column_names
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>(),
+            reader,
+            &options,
+        )
+        .await?;
+
+        Ok(Python::with_gil(|py| {
+            let d: Py<PyDict> = load_result_into_dict(py, bres).into();
+            d
+        }))
+    })
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn copy_query_to_sql<'a>(
+    py: Python<'a>,
+    conn: &MsSqlConnection,
+    query: String,
+    args: Vec<&PyAny>,
+    connection_string: String,
+    table_name: String,
+    column_names: Vec<String>,
+    aad_token: Option<String>,
+    bulk_options: Option<&PyDict>,
+    progress_callback: Option<PyObject>,
+    create_table: bool,
+) -> PyResult<&'a PyAny> {
+    let tds_args = to_exec_args(args)?;
+    let mut options = bulk_options_from_py(&connection_string, bulk_options)?;
+    options.progress = progress_from_py(progress_callback);
+    options.reconnect = Some(ConnectInfo {
+        connection_string: connection_string.clone(),
+        aad_token: aad_token.clone(),
+    });
+
+    let mutex = conn.0.clone();
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut db_clients = options.connect(&connection_string, aad_token).await?;
+        let mut rcon = mutex.lock().await;
+        let mut conn = rcon.take().await?;
+        let res = sql_copy::copy_query(
+            &mut conn,
+            &query,
+            tds_args
+                .iter()
+                .map(|x| x.0.borrow() as &dyn ToSql)
+                .collect::<Vec<&dyn ToSql>>()
+                .as_slice(),
+            &mut db_clients,
+            &table_name,
+            &column_names
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>(),
+            &options,
+            create_table,
+        )
+        .await;
+        rcon.give_back_if_done(conn, &res);
+        let bres = res?;
+
+        Ok(Python::with_gil(|py| {
+            let d: Py<PyDict> = load_result_into_dict(py, bres).into();
+            d
+        }))
+    })
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn insert_delta_to_sql<'a>(
+    py: Python<'a>,
+    connection_string: String,
+    delta_path: String,
+    table_name: String,
+    column_names: Vec<String>,
+    aad_token: Option<String>,
+    bulk_options: Option<&PyDict>,
+    progress_callback: Option<PyObject>,
+    version: Option<i64>,
+    timestamp_ms: Option<i64>,
+) -> PyResult<&'a PyAny> {
+    let version = match (version, timestamp_ms) {
+        (Some(_), Some(_)) => {
+            return Err(PyErr::new::<PyValueError, _>(
+                "Only one of version and timestamp can be given",
+            ))
+        }
+        (Some(v), None) => DeltaVersion::Version(v),
+        (None, Some(ts)) => DeltaVersion::Timestamp(ts),
+        (None, None) => DeltaVersion::Latest,
+    };
+    let mut options = bulk_options_from_py(&connection_string, bulk_options)?;
+    options.progress = progress_from_py(progress_callback);
+    options.reconnect = Some(ConnectInfo {
+        connection_string: connection_string.clone(),
+        aad_token: aad_token.clone(),
+    });
+
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut db_clients = options.connect(&connection_string, aad_token).await?;
+        let bres = bulk_insert::bulk_insert_delta(
+            &mut db_clients,
+            &table_name,
+            &column_names
+                .iter()
+                .map(|x| x.as_str())
+                .collect::<Vec<&str>>(),
+            std::path::Path::new(&delta_path),
+            version,
+            &options,
+        )
+        .await?;
+
+        Ok(Python::with_gil(|py| {
+            let d: Py<PyDict> = load_result_into_dict(py, bres).into();
+            d
+        }))
+    })
+}
+
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+fn insert_files_to_sql<'a>(
+    py: Python<'a>,
+    connection_string: String,
+    path: String,
+    table_name: String,
+    column_names: Vec<String>,
+    aad_token: Option<String>,
+    bulk_options: Option<&PyDict>,
+    progress_callback: Option<PyObject>,
+    file_options: Option<&PyDict>,
+) -> PyResult<&'a PyAny> {
+    let source = file_source_from_py(&path, file_options)?;
+    let mut options = bulk_options_from_py(&connection_string, bulk_options)?;
+    options.progress = progress_from_py(progress_callback);
+    options.reconnect = Some(ConnectInfo {
+        connection_string: connection_string.clone(),
+        aad_token: aad_token.clone(),
+    });
+
+    pyo3_asyncio::tokio::future_into_py(py, async move {
+        let mut db_clients = options.connect(&connection_string, aad_token).await?;
+        let bres = bulk_insert::bulk_insert_files(
+            &mut db_clients,
             &table_name,
             &column_names
                 .iter()
                 .map(|x| x.as_str())
                 .collect::<Vec<&str>>(),
-            &mut reader,
+            &source,
+            &options,
         )
         .await?;
 
         Ok(Python::with_gil(|py| {
-            let d: Py<PyDict> = into_dict(py, bres).into();
+            let d: Py<PyDict> = load_result_into_dict(py, bres).into();
             d
         }))
     })
@@ -426,9 +1370,16 @@ fn _lowlevel(_py: Python, m: &PyModule) -> PyResult<()> {
     pyo3_log::init();
     m.add_function(wrap_pyfunction!(insert_arrow_stream_to_sql, m)?)?;
     m.add_function(wrap_pyfunction!(connect_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(copy_query_to_sql, m)?)?;
     m.add_function(wrap_pyfunction!(execute_sql, m)?)?;
     m.add_function(wrap_pyfunction!(execute_sql_with_result, m)?)?;
+    m.add_function(wrap_pyfunction!(export_query_to_parquet, m)?)?;
+    m.add_function(wrap_pyfunction!(export_query_to_arrow, m)?)?;
+    m.add_function(wrap_pyfunction!(export_query_to_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(export_query_to_delta, m)?)?;
     m.add_function(wrap_pyfunction!(insert_arrow_reader_to_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(insert_delta_to_sql, m)?)?;
+    m.add_function(wrap_pyfunction!(insert_files_to_sql, m)?)?;
 
     Ok(())
 }
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow::datatypes::Schema;
+use tiberius::ColumnType;
+
+use crate::watermark::WatermarkResult;
+
+/// Counters of a load. Times of parallel connections are summed up
+#[derive(Debug, Clone, Default)]
+pub struct LoadStats {
+    /// Rows received from the source, before any filtering
+    pub rows_read: u64,
+    /// Rows skipped because an earlier attempt of the load committed them already
+    pub rows_skipped: u64,
+    /// Rows the server reported as inserted
+    pub rows_written: u64,
+    /// Number of batches sent, each batch is one bulk insert
+    pub batches: u64,
+    /// Bytes received over http, 0 for other sources
+    pub bytes_received: u64,
+    /// Time spent converting arrow data ahead of sending, values converted while the rows are sent count as
+    /// network time
+    pub conversion_time: Duration,
+    /// Time spent sending rows to the server
+    pub network_time: Duration,
+    /// Time spent waiting for the server to commit the batches
+    pub server_time: Duration,
+}
+
+impl LoadStats {
+    pub fn add(&mut self, other: &LoadStats) {
+        self.rows_read += other.rows_read;
+        self.rows_skipped += other.rows_skipped;
+        self.rows_written += other.rows_written;
+        self.batches += other.batches;
+        self.bytes_received += other.bytes_received;
+        self.conversion_time += other.conversion_time;
+        self.network_time += other.network_time;
+        self.server_time += other.server_time;
+    }
+}
+
+/// A column of the target table and the source column it was loaded from
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub name: String,
+    pub column_type: ColumnType,
+    /// Arrow type of the source column, None if the source has no such column and nulls were inserted
+    pub arrow_type: Option<arrow::datatypes::DataType>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadResult {
+    /// Schema of the source
+    pub schema: Arc<Schema>,
+    pub columns: Vec<ColumnMapping>,
+    pub stats: LoadStats,
+    pub watermark: Option<WatermarkResult>,
+}
+
+impl LoadResult {
+    pub(crate) fn new(
+        schema: Arc<Schema>,
+        collist: &[(String, ColumnType)],
+        stats: LoadStats,
+    ) -> Self {
+        let columns = collist
+            .iter()
+            .map(|(name, column_type)| ColumnMapping {
+                name: name.clone(),
+                column_type: *column_type,
+                arrow_type: schema
+                    .field_with_name(name)
+                    .ok()
+                    .map(|f| f.data_type().clone()),
+            })
+            .collect();
+        Self {
+            schema,
+            columns,
+            stats,
+            watermark: None,
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_to_parquet_schema;
+use parquet::arrow::arrow_writer::{compute_leaves, get_column_writers, ArrowColumnWriter};
+use parquet::basic::{Compression, GzipLevel, LogicalType, Type as PhysicalType, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::{WriterProperties, WriterPropertiesPtr};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::{SchemaDescriptor, Type};
+use tokio::sync::mpsc;
+
+use crate::error::LakeApi2SqlError;
+use crate::export::{split_file_name, Written};
+use crate::sql_to_arrow::is_uuid;
+
+/// How to write parquet files
+#[derive(Debug, Clone)]
+pub struct ParquetExportOptions {
+    /// Maximum rows of a row group
+    pub row_group_rows: usize,
+    pub compression: Compression,
+    /// Starts a new file after this many rows
+    pub max_rows_per_file: Option<u64>,
+    /// Starts a new file once a file gets larger than this. Checked after each batch, so files
+    /// can be a little larger
+    pub max_bytes_per_file: Option<u64>,
+}
+
+impl Default for ParquetExportOptions {
+    fn default() -> Self {
+        Self {
+            row_group_rows: 1024 * 1024,
+            compression: Compression::SNAPPY,
+            max_rows_per_file: None,
+            max_bytes_per_file: None,
+        }
+    }
+}
+
+impl ParquetExportOptions {
+    /// Whether the export may be split into more than one file
+    pub fn splits(&self) -> bool {
+        self.max_rows_per_file.is_some() || self.max_bytes_per_file.is_some()
+    }
+}
+
+/// Parses a codec name, one of none, snappy, gzip, lz4 or zstd. The level is used by gzip and zstd
+pub fn compression_from_name(
+    name: &str,
+    level: Option<i32>,
+) -> Result<Compression, LakeApi2SqlError> {
+    let invalid = |e: ParquetError| LakeApi2SqlError::InvalidOptions(e.to_string());
+    Ok(match name.to_lowercase().as_str() {
+        "none" | "uncompressed" => Compression::UNCOMPRESSED,
+        "snappy" => Compression::SNAPPY,
+        "gzip" => Compression::GZIP(match level {
+            Some(l) => GzipLevel::try_new(l as u32).map_err(invalid)?,
+            None => GzipLevel::default(),
+        }),
+        // the lz4 codec with hadoop framing is deprecated, readers expect lz4_raw
+        "lz4" | "lz4_raw" => Compression::LZ4_RAW,
+        "zstd" => Compression::ZSTD(match level {
+            Some(l) => ZstdLevel::try_new(l).map_err(invalid)?,
+            None => ZstdLevel::default(),
+        }),
+        n => {
+            return Err(LakeApi2SqlError::InvalidOptions(format!(
+                "unknown compression {n}, use none, snappy, gzip, lz4 or zstd"
+            )))
+        }
+    })
+}
+
+/// The parquet schema of an arrow schema. Uuid columns get the uuid logical type, which the arrow
+/// writer does not know about
+pub fn parquet_schema(schema: &Schema) -> Result<SchemaDescriptor, LakeApi2SqlError> {
+    let descr = arrow_to_parquet_schema(schema)?;
+    let root = descr.root_schema();
+    let fields = root
+        .get_fields()
+        .iter()
+        .zip(schema.fields().iter())
+        .map(|(t, f)| {
+            if !is_uuid(f) {
+                return Ok(t.clone());
+            }
+            Ok(Arc::new(
+                Type::primitive_type_builder(f.name(), PhysicalType::FIXED_LEN_BYTE_ARRAY)
+                    .with_length(16)
+                    .with_logical_type(Some(LogicalType::Uuid))
+                    .with_repetition(t.get_basic_info().repetition())
+                    .build()?,
+            ))
+        })
+        .collect::<Result<Vec<_>, ParquetError>>()?;
+    let root = Type::group_type_builder(root.name())
+        .with_fields(fields)
+        .build()?;
+    Ok(SchemaDescriptor::new(Arc::new(root)))
+}
+
+struct OpenFile {
+    path: PathBuf,
+    writer: SerializedFileWriter<BufWriter<File>>,
+    columns: Vec<ArrowColumnWriter>,
+    group_rows: usize,
+    rows: u64,
+}
+
+/// Writes record batches to one parquet file, or to several if the options split the export
+pub struct ParquetFilesWriter {
+    path: PathBuf,
+    options: ParquetExportOptions,
+    schema: Option<SchemaRef>,
+    parquet_schema: Option<Arc<SchemaDescriptor>>,
+    props: WriterPropertiesPtr,
+    current: Option<OpenFile>,
+    written: Written,
+}
+
+impl ParquetFilesWriter {
+    pub fn new(path: &Path, options: ParquetExportOptions) -> Self {
+        let props = WriterProperties::builder()
+            .set_compression(options.compression)
+            .set_max_row_group_size(options.row_group_rows)
+            .build();
+        Self {
+            path: path.to_owned(),
+            options,
+            schema: None,
+            parquet_schema: None,
+            props: Arc::new(props),
+            current: None,
+            written: Written::default(),
+        }
+    }
+
+    fn open(&mut self, schema: &SchemaRef) -> Result<OpenFile, LakeApi2SqlError> {
+        let parquet_schema = match &self.parquet_schema {
+            Some(s) => s.clone(),
+            None => {
+                let s = Arc::new(parquet_schema(schema)?);
+                self.schema = Some(schema.clone());
+                self.parquet_schema = Some(s.clone());
+                s
+            }
+        };
+        let path = if self.options.splits() {
+            split_file_name(&self.path, self.written.files.len())
+        } else {
+            self.path.clone()
+        };
+        let writer = SerializedFileWriter::new(
+            BufWriter::new(File::create(&path)?),
+            parquet_schema.root_schema_ptr(),
+            self.props.clone(),
+        )?;
+        self.written.files.push(path.clone());
+        Ok(OpenFile {
+            path,
+            writer,
+            columns: get_column_writers(&parquet_schema, &self.props, schema)?,
+            group_rows: 0,
+            rows: 0,
+        })
+    }
+
+    fn flush_row_group(&self, file: &mut OpenFile) -> Result<(), LakeApi2SqlError> {
+        let (Some(schema), Some(parquet_schema)) = (&self.schema, &self.parquet_schema) else {
+            return Ok(());
+        };
+        let columns = std::mem::replace(
+            &mut file.columns,
+            get_column_writers(parquet_schema, &self.props, schema)?,
+        );
+        let mut row_group = file.writer.next_row_group()?;
+        for c in columns {
+            c.close()?.append_to_row_group(&mut row_group)?;
+        }
+        row_group.close()?;
+        file.group_rows = 0;
+        Ok(())
+    }
+
+    fn close_file(&mut self, mut file: OpenFile) -> Result<(), LakeApi2SqlError> {
+        if file.group_rows > 0 {
+            self.flush_row_group(&mut file)?;
+        }
+        file.writer.close()?;
+        self.written.bytes += std::fs::metadata(&file.path)?.len();
+        Ok(())
+    }
+
+    fn file_full(&self, file: &OpenFile) -> bool {
+        if let Some(max) = self.options.max_rows_per_file {
+            if file.rows >= max {
+                return true;
+            }
+        }
+        match self.options.max_bytes_per_file {
+            Some(max) => {
+                let buffered: usize = file
+                    .columns
+                    .iter()
+                    .map(|c| c.get_estimated_total_bytes())
+                    .sum();
+                (file.writer.bytes_written() + buffered) as u64 >= max
+            }
+            None => false,
+        }
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), LakeApi2SqlError> {
+        let mut offset = 0;
+        // an empty batch still creates the first file, so the schema gets written
+        while offset < batch.num_rows() || (self.written.files.is_empty() && self.current.is_none())
+        {
+            let mut file = match self.current.take() {
+                Some(f) => f,
+                None => self.open(&batch.schema())?,
+            };
+            let mut len =
+                (batch.num_rows() - offset).min(self.options.row_group_rows - file.group_rows);
+            if let Some(max) = self.options.max_rows_per_file {
+                len = len.min((max - file.rows) as usize);
+            }
+            let slice = batch.slice(offset, len);
+            let mut columns = file.columns.iter_mut();
+            for (field, array) in slice.schema().fields().iter().zip(slice.columns()) {
+                for leaf in compute_leaves(field, array)? {
+                    columns
+                        .next()
+                        .expect("a column writer for each leaf")
+                        .write(&leaf)?;
+                }
+            }
+            file.group_rows += len;
+            file.rows += len as u64;
+            offset += len;
+            if file.group_rows >= self.options.row_group_rows {
+                self.flush_row_group(&mut file)?;
+            }
+            if len > 0 && self.file_full(&file) {
+                self.close_file(file)?;
+            } else {
+                self.current = Some(file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the last file and returns what was written
+    pub fn finish(mut self) -> Result<Written, LakeApi2SqlError> {
+        if let Some(file) = self.current.take() {
+            self.close_file(file)?;
+        }
+        Ok(self.written)
+    }
+}
+
+/// Writes all batches of the channel, for [`crate::export::export_query`]
+pub fn write_parquet(
+    path: &Path,
+    options: ParquetExportOptions,
+    mut rx: mpsc::Receiver<RecordBatch>,
+) -> Result<Written, LakeApi2SqlError> {
+    let mut writer = ParquetFilesWriter::new(path, options);
+    while let Some(batch) = rx.blocking_recv() {
+        writer.write(&batch)?;
+    }
+    writer.finish()
+}
@@ -0,0 +1,85 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::checkpoint::CheckpointTracker;
+use crate::error::LakeApi2SqlError;
+
+/// Cumulative state of a load, reported after each committed batch
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub rows_written: u64,
+    pub batches: u64,
+    /// Bytes received over http, 0 for other sources
+    pub bytes_received: u64,
+    /// Time since the load started
+    pub elapsed: Duration,
+}
+
+/// Gets called after each committed batch. With parallelism the callback is called from several
+/// connections concurrently, the counters are always cumulative over all of them
+pub trait ProgressCallback: Send + Sync {
+    fn on_progress(&self, progress: &Progress);
+}
+
+impl Debug for dyn ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Counters shared by all connections of one load
+pub(crate) struct ProgressTracker {
+    start: Instant,
+    rows_written: AtomicU64,
+    batches: AtomicU64,
+    bytes_received: Arc<AtomicU64>,
+    callback: Option<Arc<dyn ProgressCallback>>,
+    checkpoint: Option<CheckpointTracker>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(callback: Option<Arc<dyn ProgressCallback>>) -> Self {
+        Self {
+            start: Instant::now(),
+            rows_written: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+            bytes_received: Arc::new(AtomicU64::new(0)),
+            callback,
+            checkpoint: None,
+        }
+    }
+
+    pub(crate) fn with_checkpoint(mut self, checkpoint: CheckpointTracker) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Counter to be increased by the http source
+    pub(crate) fn bytes_counter(&self) -> Arc<AtomicU64> {
+        self.bytes_received.clone()
+    }
+
+    pub(crate) fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// `seq` is the position of the batch in the stream, starting at 0
+    pub(crate) fn batch_committed(&self, seq: u64, rows: u64) -> Result<(), LakeApi2SqlError> {
+        if let Some(cp) = &self.checkpoint {
+            cp.batch_committed(seq, rows)?;
+        }
+        let rows_written = self.rows_written.fetch_add(rows, Ordering::Relaxed) + rows;
+        let batches = self.batches.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(cb) = &self.callback {
+            cb.on_progress(&Progress {
+                rows_written,
+                batches,
+                bytes_received: self.bytes_received(),
+                elapsed: self.start.elapsed(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use arrow::compute::concat_batches;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+/// Slices and concatenates incoming batches so that all but the last batch have exactly `batch_rows` rows.
+/// Without `batch_rows` the batches are passed through unchanged
+pub(crate) struct Rebatcher {
+    batch_rows: Option<usize>,
+    pending: Vec<RecordBatch>,
+    pending_rows: usize,
+}
+
+impl Rebatcher {
+    pub(crate) fn new(batch_rows: Option<usize>) -> Self {
+        Self {
+            batch_rows: batch_rows.filter(|x| *x > 0),
+            pending: vec![],
+            pending_rows: 0,
+        }
+    }
+
+    /// Adds a batch and returns all batches that are complete by now
+    pub(crate) fn push(&mut self, batch: RecordBatch) -> Result<Vec<RecordBatch>, ArrowError> {
+        let batch_rows = match self.batch_rows {
+            Some(b) => b,
+            None => return Ok(vec![batch]),
+        };
+        if batch.num_rows() == 0 {
+            return Ok(vec![]);
+        }
+        self.pending_rows += batch.num_rows();
+        self.pending.push(batch);
+        if self.pending_rows < batch_rows {
+            return Ok(vec![]);
+        }
+        let all = self.take_pending()?;
+        let mut res = Vec::with_capacity(all.num_rows() / batch_rows);
+        let mut offset = 0;
+        while all.num_rows() - offset >= batch_rows {
+            res.push(all.slice(offset, batch_rows));
+            offset += batch_rows;
+        }
+        if offset < all.num_rows() {
+            self.pending_rows = all.num_rows() - offset;
+            self.pending.push(all.slice(offset, self.pending_rows));
+        }
+        Ok(res)
+    }
+
+    /// Returns the remaining rows, if any
+    pub(crate) fn finish(mut self) -> Result<Option<RecordBatch>, ArrowError> {
+        if self.pending_rows == 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.take_pending()?))
+    }
+
+    fn take_pending(&mut self) -> Result<RecordBatch, ArrowError> {
+        let pending = std::mem::take(&mut self.pending);
+        self.pending_rows = 0;
+        if pending.len() == 1 {
+            return Ok(pending.into_iter().next().unwrap());
+        }
+        concat_batches(&pending[0].schema(), &pending)
+    }
+}
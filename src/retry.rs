@@ -0,0 +1,109 @@
+use std::future::Future;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use crate::error::LakeApi2SqlError;
+use crate::timeout::timeout_from_con_str;
+
+/// Error numbers Azure SQL and SQL Server report for transient conditions, eg a failover or throttling.
+/// See https://learn.microsoft.com/en-us/azure/azure-sql/database/troubleshoot-common-errors-issues
+pub const TRANSIENT_ERROR_CODES: &[u32] = &[
+    1205, 4060, 4221, 10053, 10054, 10060, 10928, 10929, 40143, 40197, 40501, 40540, 40613, 49918,
+    49919, 49920,
+];
+
+/// Retries with exponential backoff: `initial_backoff`, then twice as long each time up to `max_backoff`
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 0 disables retries
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Server error numbers that are retried. Connection resets are always retried
+    pub error_codes: Vec<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            error_codes: TRANSIENT_ERROR_CODES.to_vec(),
+        }
+    }
+}
+
+fn is_connection_reset(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+impl RetryPolicy {
+    /// Default policy, `ConnectRetryCount` and `ConnectRetryInterval` (seconds) of the connection string
+    /// override the number of retries and the initial backoff
+    pub fn from_con_str(con_str: &str) -> Self {
+        let mut res = Self::default();
+        if let Some(count) = con_str
+            .split(';')
+            .filter_map(|part| part.split_once('='))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("ConnectRetryCount"))
+            .and_then(|(_, v)| v.trim().parse::<u32>().ok())
+        {
+            res.max_retries = count;
+        }
+        if let Some(interval) = timeout_from_con_str(con_str, &["ConnectRetryInterval"]) {
+            res.initial_backoff = interval;
+        }
+        res
+    }
+
+    pub fn is_transient(&self, err: &LakeApi2SqlError) -> bool {
+        match err {
+            LakeApi2SqlError::TiberiusError(tiberius::error::Error::Server(e)) => {
+                self.error_codes.contains(&e.code())
+            }
+            LakeApi2SqlError::TiberiusError(tiberius::error::Error::Io { kind, .. }) => {
+                is_connection_reset(*kind)
+            }
+            LakeApi2SqlError::IOError(e) => is_connection_reset(e.kind()),
+            _ => false,
+        }
+    }
+
+    /// Time to wait before retry number `attempt`, starting at 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+
+    pub fn should_retry(&self, err: &LakeApi2SqlError, attempt: u32) -> bool {
+        attempt < self.max_retries && self.is_transient(err)
+    }
+
+    /// Runs `f` until it succeeds, fails with an error that is not transient or runs out of retries
+    pub async fn run<T, F, Fut>(&self, what: &str, mut f: F) -> Result<T, LakeApi2SqlError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, LakeApi2SqlError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match f().await {
+                Err(e) if self.should_retry(&e, attempt) => {
+                    let wait = self.backoff(attempt);
+                    log::warn!("{what}: transient error {e}, retrying in {:?}", wait);
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
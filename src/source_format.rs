@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::csv::reader::Format;
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::json::reader::infer_json_schema_from_seekable;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+
+use crate::error::LakeApi2SqlError;
+
+/// Rows read to infer the schema of csv and json data
+const INFER_ROWS: usize = 1000;
+
+/// Format of the source data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    /// Arrow IPC stream format, the default
+    ArrowStream,
+    /// Arrow IPC file format, needs random access
+    ArrowFile,
+    /// Needs random access
+    Parquet,
+    /// One json object per line, the schema is inferred from the first rows
+    NdJson,
+    /// The schema is inferred from the first rows if not given
+    Csv,
+}
+
+impl DataFormat {
+    /// Plain `json` and `tsv` are not accepted, a json document isn't ndjson and tsv needs a tab delimiter
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "arrow_stream" | "arrows" => Some(Self::ArrowStream),
+            "arrow_file" | "arrow" | "feather" | "ipc" => Some(Self::ArrowFile),
+            "parquet" | "pq" => Some(Self::Parquet),
+            "ndjson" | "jsonl" => Some(Self::NdJson),
+            "csv" | "txt" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    /// Detects the format by the file extension, eg `.parquet` or `.arrow`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+
+    /// Detects the format by the `Content-Type` of a http response, parameters like charset are ignored
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime.to_lowercase().as_str() {
+            "application/vnd.apache.arrow.stream" => Some(Self::ArrowStream),
+            "application/vnd.apache.arrow.file" => Some(Self::ArrowFile),
+            "application/vnd.apache.parquet" | "application/parquet" | "application/x-parquet" => {
+                Some(Self::Parquet)
+            }
+            "application/x-ndjson"
+            | "application/ndjson"
+            | "application/jsonl"
+            | "application/x-jsonlines" => Some(Self::NdJson),
+            "text/csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    /// The `Content-Type` of data in this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::ArrowStream => "application/vnd.apache.arrow.stream",
+            Self::ArrowFile => "application/vnd.apache.arrow.file",
+            Self::Parquet => "application/vnd.apache.parquet",
+            Self::NdJson => "application/x-ndjson",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    /// Whether the data has to be spooled to a temp file before it can be read
+    pub fn needs_spooling(&self) -> bool {
+        !matches!(self, Self::ArrowStream)
+    }
+}
+
+/// How to read a file
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// Only these columns are read, all if None. For parquet the other columns are not even loaded
+    pub columns: Option<Vec<String>>,
+    /// Schema of csv and json data, inferred from the first rows if None
+    pub schema: Option<SchemaRef>,
+    pub csv_delimiter: u8,
+    pub csv_quote: u8,
+    pub csv_has_header: bool,
+    /// Rows per batch for parquet, csv and json. Parquet files are read one row group at a time
+    pub batch_size: usize,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            schema: None,
+            csv_delimiter: b',',
+            csv_quote: b'"',
+            csv_has_header: true,
+            batch_size: 8192,
+        }
+    }
+}
+
+/// Selects columns of the batches of a reader by name
+struct Projected {
+    inner: Box<dyn RecordBatchReader + Send>,
+    indices: Vec<usize>,
+    schema: SchemaRef,
+}
+
+impl Iterator for Projected {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.inner.next()?.and_then(|b| b.project(&self.indices)))
+    }
+}
+
+impl RecordBatchReader for Projected {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+fn project(
+    reader: Box<dyn RecordBatchReader + Send>,
+    columns: &Option<Vec<String>>,
+) -> Result<Box<dyn RecordBatchReader + Send>, LakeApi2SqlError> {
+    let Some(columns) = columns else {
+        return Ok(reader);
+    };
+    let schema = reader.schema();
+    let indices = columns
+        .iter()
+        .map(|c| schema.index_of(c))
+        .collect::<Result<Vec<_>, ArrowError>>()?;
+    Ok(Box::new(Projected {
+        schema: Arc::new(schema.project(&indices)?),
+        inner: reader,
+        indices,
+    }))
+}
+
+/// Opens a reader for a seekable file in the given format
+pub(crate) fn open_file(
+    format: DataFormat,
+    file: File,
+    options: &ReadOptions,
+) -> Result<Box<dyn RecordBatchReader + Send>, LakeApi2SqlError> {
+    let reader: Box<dyn RecordBatchReader + Send> = match format {
+        DataFormat::Parquet => {
+            let builder =
+                ParquetRecordBatchReaderBuilder::try_new(file)?.with_batch_size(options.batch_size);
+            let builder = match &options.columns {
+                Some(columns) => {
+                    let indices = columns
+                        .iter()
+                        .map(|c| builder.schema().index_of(c))
+                        .collect::<Result<Vec<_>, ArrowError>>()?;
+                    let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+                    builder.with_projection(mask)
+                }
+                None => builder,
+            };
+            // the projection keeps the file order of the columns
+            return project(Box::new(builder.build()?), &options.columns);
+        }
+        DataFormat::ArrowStream => Box::new(StreamReader::try_new(BufReader::new(file), None)?),
+        DataFormat::ArrowFile => Box::new(FileReader::try_new(file, None)?),
+        DataFormat::NdJson => {
+            let mut reader = BufReader::new(file);
+            let schema = match &options.schema {
+                Some(s) => s.clone(),
+                None => Arc::new(infer_json_schema_from_seekable(&mut reader, Some(INFER_ROWS))?.0),
+            };
+            Box::new(
+                arrow::json::ReaderBuilder::new(schema)
+                    .with_batch_size(options.batch_size)
+                    .build(reader)?,
+            )
+        }
+        DataFormat::Csv => {
+            let mut reader = BufReader::new(file);
+            let schema = match &options.schema {
+                Some(s) => s.clone(),
+                None => {
+                    let format = Format::default()
+                        .with_header(options.csv_has_header)
+                        .with_delimiter(options.csv_delimiter)
+                        .with_quote(options.csv_quote);
+                    let (schema, _) = format.infer_schema(&mut reader, Some(INFER_ROWS))?;
+                    reader.seek(SeekFrom::Start(0))?;
+                    Arc::new(schema)
+                }
+            };
+            Box::new(
+                arrow::csv::ReaderBuilder::new(schema)
+                    .with_header(options.csv_has_header)
+                    .with_delimiter(options.csv_delimiter)
+                    .with_quote(options.csv_quote)
+                    .with_batch_size(options.batch_size)
+                    .build(reader)?,
+            )
+        }
+    };
+    project(reader, &options.columns)
+}
+
+/// Opens a reader for the data in the given format. Formats that need random access are copied to
+/// a temp file first, which gets deleted once the reader is dropped
+pub(crate) fn open_reader<R: Read + Send + 'static>(
+    format: DataFormat,
+    mut source: R,
+) -> Result<Box<dyn RecordBatchReader + Send>, LakeApi2SqlError> {
+    if !format.needs_spooling() {
+        return Ok(Box::new(StreamReader::try_new(source, None)?));
+    }
+    let mut file = tempfile::tempfile()?;
+    std::io::copy(&mut source, &mut file)?;
+    file.seek(SeekFrom::Start(0))?;
+    open_file(format, file, &ReadOptions::default())
+}
@@ -0,0 +1,129 @@
+use log::info;
+use tiberius::{Client, ToSql};
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+
+use crate::bulk_insert::{bulk_insert_query, BulkInsertOptions};
+use crate::error::LakeApi2SqlError;
+use crate::load_result::LoadResult;
+
+/// A column of a query result as the server describes it
+#[derive(Debug, Clone)]
+pub struct DescribedColumn {
+    pub name: String,
+    /// Type with length, precision and scale, eg `nvarchar(50)` or `decimal(18,2)`
+    pub type_name: String,
+    pub nullable: bool,
+    pub collation: Option<String>,
+}
+
+/// Describes the columns of the first result set of the query with `sp_describe_first_result_set`,
+/// without running it
+pub async fn describe_query(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+) -> Result<Vec<DescribedColumn>, LakeApi2SqlError> {
+    let rows = client
+        .query("EXEC sp_describe_first_result_set @tsql = @P1", &[&query])
+        .await?
+        .into_first_result()
+        .await?;
+    let mut columns = Vec::with_capacity(rows.len());
+    for row in rows {
+        if row.try_get::<bool, _>("is_hidden")?.unwrap_or(false) {
+            continue;
+        }
+        let position = row.try_get::<i32, _>("column_ordinal")?.unwrap_or_default();
+        let name = row.try_get::<&str, _>("name")?.ok_or_else(|| {
+            LakeApi2SqlError::InvalidOptions(format!("column {position} of the query has no name"))
+        })?;
+        let type_name = row.try_get::<&str, _>("system_type_name")?.ok_or_else(|| {
+            LakeApi2SqlError::InvalidOptions(format!("column {name} has no system type"))
+        })?;
+        columns.push(DescribedColumn {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+            nullable: row.try_get::<bool, _>("is_nullable")?.unwrap_or(true),
+            collation: row
+                .try_get::<&str, _>("collation_name")?
+                .map(|c| c.to_owned()),
+        });
+    }
+    Ok(columns)
+}
+
+/// Type of the column in a created table
+fn create_type(type_name: &str) -> &str {
+    match type_name {
+        // rowversion values can't be inserted
+        "timestamp" => "binary(8)",
+        // the bulk load can't tell nullable smalldatetime from datetime columns
+        "smalldatetime" => "datetime",
+        // values are sent with 7 digits, unlike datetime2 these are not rescaled to the column
+        t if t.starts_with("time(") => "time(7)",
+        t if t.starts_with("datetimeoffset(") => "datetimeoffset(7)",
+        t => t,
+    }
+}
+
+/// `CREATE TABLE` for the columns, only run if the table does not exist yet
+pub fn create_table_sql(table_name: &str, columns: &[DescribedColumn]) -> String {
+    let columns = columns
+        .iter()
+        .map(|c| {
+            let mut def = format!(
+                "[{}] {}",
+                c.name.replace(']', "]]"),
+                create_type(&c.type_name)
+            );
+            if let Some(collation) = &c.collation {
+                def.push_str(" COLLATE ");
+                def.push_str(collation);
+            }
+            def.push_str(if c.nullable { " NULL" } else { " NOT NULL" });
+            def
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!(
+        "IF OBJECT_ID(N'{}', N'U') IS NULL CREATE TABLE {table_name} ({columns})",
+        table_name.replace('\'', "''")
+    )
+}
+
+/// Copies the result of a query on the source into the table, see [`bulk_insert_query`]. With
+/// `create_table` a missing table is created from the source types first. The types are read with
+/// `sp_describe_first_result_set`, which doesn't know the types of query parameters
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_query(
+    source: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    db_clients: &mut [Client<Compat<TcpStream>>],
+    table_name: &str,
+    column_names: &[&str],
+    options: &BulkInsertOptions,
+    create_table: bool,
+) -> Result<LoadResult, LakeApi2SqlError> {
+    if create_table {
+        if !params.is_empty() {
+            return Err(LakeApi2SqlError::InvalidOptions(
+                "create_table can't be combined with query arguments".to_owned(),
+            ));
+        }
+        let columns = describe_query(source, query).await?;
+        let create = create_table_sql(table_name, &columns);
+        info!("{table_name}: {create}");
+        db_clients[0].execute(create, &[]).await?;
+    }
+    bulk_insert_query(
+        source,
+        query,
+        params,
+        db_clients,
+        table_name,
+        column_names,
+        options,
+    )
+    .await
+}
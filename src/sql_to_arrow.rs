@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayBuilder, ArrayRef, AsArray, BinaryBuilder, BooleanBuilder, Date32Builder,
+    Decimal128Builder, FixedSizeBinaryBuilder, Float32Builder, Float64Builder, Int16Builder,
+    Int32Builder, Int64Builder, StringArray, StringBuilder, Time64MicrosecondBuilder,
+    TimestampMicrosecondBuilder, UInt8Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use futures::TryStreamExt;
+use tiberius::time::time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+use tiberius::{Client, Column, ColumnData, ColumnType, FromSql, QueryItem, ToSql};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::Compat;
+
+use crate::error::LakeApi2SqlError;
+
+/// Extension name of uuid columns, which are `FixedSizeBinary(16)`
+pub const UUID_EXTENSION: &str = "arrow.uuid";
+
+/// Scale of decimal columns that are null in the whole first batch, the scale of the values is
+/// not known then
+const FALLBACK_DECIMAL_SCALE: i8 = 10;
+
+/// Whether the field is a uuid column
+pub fn is_uuid(field: &Field) -> bool {
+    field
+        .metadata()
+        .get("ARROW:extension:name")
+        .is_some_and(|n| n.as_str() == UUID_EXTENSION)
+}
+
+/// The values of a uuid column as strings like `6f9619ff-8b86-d011-b42d-00c04fc964ff`
+pub fn uuid_strings(array: &ArrayRef) -> ArrayRef {
+    let strings: StringArray = array
+        .as_fixed_size_binary()
+        .iter()
+        .map(|v| {
+            v.and_then(|b| uuid::Uuid::from_slice(b).ok())
+                .map(|u| u.to_string())
+        })
+        .collect();
+    Arc::new(strings)
+}
+
+fn uuid_field(name: &str) -> Field {
+    Field::new(name, DataType::FixedSizeBinary(16), true).with_metadata(HashMap::from([(
+        "ARROW:extension:name".to_owned(),
+        UUID_EXTENSION.to_owned(),
+    )]))
+}
+
+/// Arrow type of a value. Timestamps get microseconds, the 100ns precision of datetime2 is cut,
+/// but dates like 9999-12-31 still fit
+fn value_type(value: &ColumnData<'static>) -> Option<DataType> {
+    Some(match value {
+        ColumnData::U8(Some(_)) => DataType::UInt8,
+        ColumnData::I16(Some(_)) => DataType::Int16,
+        ColumnData::I32(Some(_)) => DataType::Int32,
+        ColumnData::I64(Some(_)) => DataType::Int64,
+        ColumnData::F32(Some(_)) => DataType::Float32,
+        ColumnData::F64(Some(_)) => DataType::Float64,
+        ColumnData::Bit(Some(_)) => DataType::Boolean,
+        ColumnData::String(Some(_)) | ColumnData::Xml(Some(_)) => DataType::Utf8,
+        ColumnData::Guid(Some(_)) => DataType::FixedSizeBinary(16),
+        ColumnData::Binary(Some(_)) => DataType::Binary,
+        ColumnData::Numeric(Some(n)) => DataType::Decimal128(38, n.scale() as i8),
+        ColumnData::DateTime(Some(_))
+        | ColumnData::SmallDateTime(Some(_))
+        | ColumnData::DateTime2(Some(_)) => DataType::Timestamp(TimeUnit::Microsecond, None),
+        ColumnData::DateTimeOffset(Some(_)) => {
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        }
+        ColumnData::Date(Some(_)) => DataType::Date32,
+        ColumnData::Time(Some(_)) => DataType::Time64(TimeUnit::Microsecond),
+        _ => return None,
+    })
+}
+
+/// Arrow type of a column without any values. Nullable integer and float columns don't tell their
+/// size, so the largest one is used
+fn column_type(column_type: ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Bit | ColumnType::Bitn => DataType::Boolean,
+        ColumnType::Int1 => DataType::UInt8,
+        ColumnType::Int2 => DataType::Int16,
+        ColumnType::Int4 => DataType::Int32,
+        ColumnType::Int8 | ColumnType::Intn => DataType::Int64,
+        ColumnType::Float4 => DataType::Float32,
+        ColumnType::Float8 | ColumnType::Floatn | ColumnType::Money | ColumnType::Money4 => {
+            DataType::Float64
+        }
+        ColumnType::Decimaln | ColumnType::Numericn => {
+            DataType::Decimal128(38, FALLBACK_DECIMAL_SCALE)
+        }
+        ColumnType::Guid => DataType::FixedSizeBinary(16),
+        ColumnType::Datetime
+        | ColumnType::Datetime4
+        | ColumnType::Datetimen
+        | ColumnType::Datetime2 => DataType::Timestamp(TimeUnit::Microsecond, None),
+        ColumnType::DatetimeOffsetn => {
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        }
+        ColumnType::Daten => DataType::Date32,
+        ColumnType::Timen => DataType::Time64(TimeUnit::Microsecond),
+        ColumnType::BigVarBin | ColumnType::BigBinary | ColumnType::Image => DataType::Binary,
+        _ => DataType::Utf8,
+    }
+}
+
+/// Schema of a result, types are taken from the values of the first rows if possible
+pub fn result_schema(columns: &[Column], first_rows: &[Vec<ColumnData<'static>>]) -> SchemaRef {
+    let fields: Vec<Field> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let dt = first_rows
+                .iter()
+                .find_map(|r| r.get(i).and_then(value_type))
+                .unwrap_or_else(|| column_type(c.column_type()));
+            match dt {
+                DataType::FixedSizeBinary(16) => uuid_field(c.name()),
+                dt => Field::new(c.name(), dt, true),
+            }
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn unix_micros(dt: PrimitiveDateTime) -> i64 {
+    (dt.assume_utc().unix_timestamp_nanos() / 1000) as i64
+}
+
+fn value_i64(value: &ColumnData<'static>) -> Option<Option<i64>> {
+    match value {
+        ColumnData::U8(v) => Some(v.map(i64::from)),
+        ColumnData::I16(v) => Some(v.map(i64::from)),
+        ColumnData::I32(v) => Some(v.map(i64::from)),
+        ColumnData::I64(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn value_f64(value: &ColumnData<'static>) -> Option<Option<f64>> {
+    match value {
+        ColumnData::F32(v) => Some(v.map(f64::from)),
+        ColumnData::F64(v) => Some(*v),
+        v => value_i64(v).map(|v| v.map(|i| i as f64)),
+    }
+}
+
+/// String representation of any value, used for columns that have no better arrow type
+fn value_string(value: &ColumnData<'static>) -> Option<String> {
+    match value {
+        ColumnData::String(v) => v.as_ref().map(|s| s.to_string()),
+        ColumnData::Xml(v) => v.as_ref().map(|s| s.to_string()),
+        ColumnData::Guid(v) => v.map(|g| g.to_string()),
+        ColumnData::Numeric(v) => v.map(|n| n.to_string()),
+        ColumnData::Bit(v) => v.map(|b| b.to_string()),
+        ColumnData::F32(v) => v.map(|f| f.to_string()),
+        ColumnData::F64(v) => v.map(|f| f.to_string()),
+        ColumnData::Binary(v) => v
+            .as_ref()
+            .map(|b| b.iter().map(|x| format!("{x:02X}")).collect()),
+        ColumnData::DateTime(Some(_))
+        | ColumnData::SmallDateTime(Some(_))
+        | ColumnData::DateTime2(Some(_)) => PrimitiveDateTime::from_sql(value)
+            .ok()
+            .flatten()
+            .map(|d| d.to_string()),
+        ColumnData::DateTimeOffset(Some(_)) => OffsetDateTime::from_sql(value)
+            .ok()
+            .flatten()
+            .map(|d| d.to_string()),
+        ColumnData::Date(Some(_)) => Date::from_sql(value).ok().flatten().map(|d| d.to_string()),
+        ColumnData::Time(Some(_)) => Time::from_sql(value).ok().flatten().map(|d| d.to_string()),
+        v => value_i64(v).flatten().map(|i| i.to_string()),
+    }
+}
+
+fn mismatch(field: &Field, value: &ColumnData<'static>) -> LakeApi2SqlError {
+    LakeApi2SqlError::SchemaMismatch(format!(
+        "column {} of type {} got a value of type {value:?}",
+        field.name(),
+        field.data_type()
+    ))
+}
+
+/// Appends tds values of one column to an arrow array
+enum ColumnBuilder {
+    UInt8(UInt8Builder),
+    Int16(Int16Builder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Boolean(BooleanBuilder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+    Uuid(FixedSizeBinaryBuilder),
+    Decimal(Decimal128Builder, i8),
+    Timestamp(TimestampMicrosecondBuilder, Option<Arc<str>>),
+    Date(Date32Builder),
+    Time(Time64MicrosecondBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(field: &Field, capacity: usize) -> Self {
+        match field.data_type() {
+            DataType::UInt8 => Self::UInt8(UInt8Builder::with_capacity(capacity)),
+            DataType::Int16 => Self::Int16(Int16Builder::with_capacity(capacity)),
+            DataType::Int32 => Self::Int32(Int32Builder::with_capacity(capacity)),
+            DataType::Int64 => Self::Int64(Int64Builder::with_capacity(capacity)),
+            DataType::Float32 => Self::Float32(Float32Builder::with_capacity(capacity)),
+            DataType::Float64 => Self::Float64(Float64Builder::with_capacity(capacity)),
+            DataType::Boolean => Self::Boolean(BooleanBuilder::with_capacity(capacity)),
+            DataType::Binary => Self::Binary(BinaryBuilder::with_capacity(capacity, 0)),
+            DataType::FixedSizeBinary(16) => {
+                Self::Uuid(FixedSizeBinaryBuilder::with_capacity(capacity, 16))
+            }
+            DataType::Decimal128(_, s) => {
+                Self::Decimal(Decimal128Builder::with_capacity(capacity), *s)
+            }
+            DataType::Timestamp(_, tz) => Self::Timestamp(
+                TimestampMicrosecondBuilder::with_capacity(capacity),
+                tz.clone(),
+            ),
+            DataType::Date32 => Self::Date(Date32Builder::with_capacity(capacity)),
+            DataType::Time64(_) => Self::Time(Time64MicrosecondBuilder::with_capacity(capacity)),
+            _ => Self::Utf8(StringBuilder::with_capacity(capacity, 0)),
+        }
+    }
+
+    fn append(
+        &mut self,
+        field: &Field,
+        value: &ColumnData<'static>,
+    ) -> Result<(), LakeApi2SqlError> {
+        let int = || value_i64(value).ok_or_else(|| mismatch(field, value));
+        match self {
+            Self::UInt8(b) => b.append_option(int()?.map(|v| v as u8)),
+            Self::Int16(b) => b.append_option(int()?.map(|v| v as i16)),
+            Self::Int32(b) => b.append_option(int()?.map(|v| v as i32)),
+            Self::Int64(b) => b.append_option(int()?),
+            Self::Float32(b) => b.append_option(
+                value_f64(value)
+                    .ok_or_else(|| mismatch(field, value))?
+                    .map(|v| v as f32),
+            ),
+            Self::Float64(b) => {
+                b.append_option(value_f64(value).ok_or_else(|| mismatch(field, value))?)
+            }
+            Self::Boolean(b) => match value {
+                ColumnData::Bit(v) => b.append_option(*v),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Utf8(b) => b.append_option(value_string(value)),
+            Self::Binary(b) => match value {
+                ColumnData::Binary(v) => b.append_option(v.as_deref()),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Uuid(b) => match value {
+                ColumnData::Guid(Some(g)) => b.append_value(g.as_bytes())?,
+                ColumnData::Guid(None) => b.append_null(),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Decimal(b, scale) => match value {
+                ColumnData::Numeric(Some(n)) => {
+                    let diff = *scale as i32 - n.scale() as i32;
+                    let v = if diff >= 0 {
+                        n.value() * 10i128.pow(diff as u32)
+                    } else {
+                        n.value() / 10i128.pow((-diff) as u32)
+                    };
+                    b.append_value(v)
+                }
+                ColumnData::Numeric(None) => b.append_null(),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Timestamp(b, _) => match value {
+                ColumnData::DateTimeOffset(Some(_)) => b.append_option(
+                    OffsetDateTime::from_sql(value)?
+                        .map(|d| (d.unix_timestamp_nanos() / 1000) as i64),
+                ),
+                ColumnData::DateTime(_)
+                | ColumnData::SmallDateTime(_)
+                | ColumnData::DateTime2(_) => {
+                    b.append_option(PrimitiveDateTime::from_sql(value)?.map(unix_micros))
+                }
+                ColumnData::DateTimeOffset(None) => b.append_null(),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Date(b) => match value {
+                ColumnData::Date(_) => b.append_option(
+                    Date::from_sql(value)?.map(|d| d.to_julian_day() - UNIX_EPOCH_JULIAN_DAY),
+                ),
+                v => return Err(mismatch(field, v)),
+            },
+            Self::Time(b) => match value {
+                ColumnData::Time(_) => b.append_option(Time::from_sql(value)?.map(|t| {
+                    let (h, m, s, ns) = t.as_hms_nano();
+                    (h as i64 * 3600 + m as i64 * 60 + s as i64) * 1_000_000 + ns as i64 / 1000
+                })),
+                v => return Err(mismatch(field, v)),
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Self::UInt8(b) => Arc::new(b.finish()),
+            Self::Int16(b) => Arc::new(b.finish()),
+            Self::Int32(b) => Arc::new(b.finish()),
+            Self::Int64(b) => Arc::new(b.finish()),
+            Self::Float32(b) => Arc::new(b.finish()),
+            Self::Float64(b) => Arc::new(b.finish()),
+            Self::Boolean(b) => Arc::new(b.finish()),
+            Self::Utf8(b) => Arc::new(b.finish()),
+            Self::Binary(b) => Arc::new(b.finish()),
+            Self::Uuid(b) => Arc::new(b.finish()),
+            Self::Decimal(b, s) => Arc::new(
+                b.finish()
+                    .with_precision_and_scale(38, *s)
+                    .expect("38 is a valid precision"),
+            ),
+            Self::Timestamp(b, tz) => Arc::new(b.finish().with_timezone_opt(tz.clone())),
+            Self::Date(b) => Arc::new(b.finish()),
+            Self::Time(b) => Arc::new(b.finish()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::UInt8(b) => b.len(),
+            Self::Int16(b) => b.len(),
+            Self::Int32(b) => b.len(),
+            Self::Int64(b) => b.len(),
+            Self::Float32(b) => b.len(),
+            Self::Float64(b) => b.len(),
+            Self::Boolean(b) => b.len(),
+            Self::Utf8(b) => b.len(),
+            Self::Binary(b) => b.len(),
+            Self::Uuid(b) => b.len(),
+            Self::Decimal(b, _) => b.len(),
+            Self::Timestamp(b, _) => b.len(),
+            Self::Date(b) => b.len(),
+            Self::Time(b) => b.len(),
+        }
+    }
+}
+
+const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+
+/// Builds record batches out of tds rows
+pub struct BatchBuilder {
+    schema: SchemaRef,
+    columns: Vec<ColumnBuilder>,
+    batch_rows: usize,
+}
+
+impl BatchBuilder {
+    pub fn new(schema: SchemaRef, batch_rows: usize) -> Self {
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|f| ColumnBuilder::new(f, batch_rows))
+            .collect();
+        Self {
+            schema,
+            columns,
+            batch_rows,
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.first().map(|c| c.len()).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn append_row(&mut self, row: &[ColumnData<'static>]) -> Result<(), LakeApi2SqlError> {
+        for ((col, field), value) in self
+            .columns
+            .iter_mut()
+            .zip(self.schema.fields().iter())
+            .zip(row.iter())
+        {
+            col.append(field, value)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the batch reached `batch_rows`
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.batch_rows
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch, LakeApi2SqlError> {
+        let columns = self.columns.iter_mut().map(|c| c.finish()).collect();
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+/// Runs a query and sends the first result set as record batches of `batch_rows` rows. The
+/// types are taken from the values of the first batch. A result without rows gets one empty
+/// batch, so the receiver always sees the schema. Returns the schema and the number of rows
+pub async fn query_batches(
+    client: &mut Client<Compat<TcpStream>>,
+    query: &str,
+    params: &[&dyn ToSql],
+    batch_rows: usize,
+    tx: mpsc::Sender<RecordBatch>,
+) -> Result<(SchemaRef, u64), LakeApi2SqlError> {
+    let mut stream = client.query(query, params).await?;
+    let mut columns: Vec<Column> = vec![];
+    let mut first_rows: Vec<Vec<ColumnData<'static>>> = vec![];
+    let mut builder: Option<BatchBuilder> = None;
+    let mut rows = 0;
+    while let Some(item) = stream.try_next().await? {
+        match item {
+            QueryItem::Metadata(m) if m.result_index() == 0 => {
+                columns = m.columns().to_vec();
+            }
+            QueryItem::Row(row) if row.result_index() == 0 => {
+                rows += 1;
+                let values: Vec<ColumnData<'static>> = row.into_iter().collect();
+                match builder.as_mut() {
+                    Some(b) => {
+                        b.append_row(&values)?;
+                        if b.is_full() {
+                            tx.send(b.finish()?).await?;
+                        }
+                    }
+                    None => {
+                        first_rows.push(values);
+                        if first_rows.len() >= batch_rows {
+                            let mut b =
+                                BatchBuilder::new(result_schema(&columns, &first_rows), batch_rows);
+                            for r in first_rows.drain(..) {
+                                b.append_row(&r)?;
+                            }
+                            tx.send(b.finish()?).await?;
+                            builder = Some(b);
+                        }
+                    }
+                }
+            }
+            // only the first result set is returned, the others are read so the client can run
+            // the next query
+            QueryItem::Metadata(_) | QueryItem::Row(_) => {}
+        }
+    }
+    let mut b = match builder {
+        Some(b) => b,
+        None => {
+            let mut b = BatchBuilder::new(result_schema(&columns, &first_rows), batch_rows);
+            for r in first_rows.drain(..) {
+                b.append_row(&r)?;
+            }
+            tx.send(b.finish()?).await?;
+            return Ok((b.schema(), rows));
+        }
+    };
+    if !b.is_empty() {
+        tx.send(b.finish()?).await?;
+    }
+    Ok((b.schema(), rows))
+}
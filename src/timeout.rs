@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+
+use crate::error::LakeApi2SqlError;
+
+/// Runs the future with an optional time limit. On timeout the future is dropped
+pub async fn with_timeout<T, F>(
+    limit: Option<Duration>,
+    what: &str,
+    fut: F,
+) -> Result<T, LakeApi2SqlError>
+where
+    F: Future<Output = Result<T, LakeApi2SqlError>>,
+{
+    match limit {
+        Some(l) => tokio::time::timeout(l, fut)
+            .await
+            .map_err(|_| LakeApi2SqlError::Timeout(format!("{what} took longer than {:?}", l)))?,
+        None => fut.await,
+    }
+}
+
+/// Reads a timeout in seconds from the connection string, eg `Connect Timeout=30`. 0 means no timeout,
+/// as in ADO.NET
+pub fn timeout_from_con_str(con_str: &str, keys: &[&str]) -> Option<Duration> {
+    con_str
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .find(|(k, _)| {
+            let k = k.trim();
+            keys.iter().any(|key| k.eq_ignore_ascii_case(key))
+        })
+        .and_then(|(_, v)| v.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+pub fn connect_timeout(con_str: &str) -> Option<Duration> {
+    timeout_from_con_str(
+        con_str,
+        &["Connect Timeout", "Connection Timeout", "Timeout"],
+    )
+}
+
+pub fn command_timeout(con_str: &str) -> Option<Duration> {
+    timeout_from_con_str(con_str, &["Command Timeout"])
+}
+
+/// Ends the stream with a `TimedOut` error if no item arrives within `idle`. `timed_out` is set so the
+/// caller can tell the timeout apart from other io errors once the reader failed
+pub fn with_idle_timeout<S, T>(
+    stream: S,
+    idle: Option<Duration>,
+    timed_out: Arc<AtomicBool>,
+) -> impl Stream<Item = io::Result<T>>
+where
+    S: Stream<Item = io::Result<T>> + Unpin,
+{
+    futures::stream::unfold(Some(stream), move |state| {
+        let timed_out = timed_out.clone();
+        async move {
+            let mut stream = state?;
+            let next = match idle {
+                Some(d) => match tokio::time::timeout(d, stream.next()).await {
+                    Ok(n) => n,
+                    Err(_) => {
+                        timed_out.store(true, Ordering::Relaxed);
+                        let err = io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("no data received for {:?}", d),
+                        );
+                        return Some((Err(err), None));
+                    }
+                },
+                None => stream.next().await,
+            };
+            next.map(|item| (item, Some(stream)))
+        }
+    })
+}
@@ -0,0 +1,117 @@
+use arrow::array::{Scalar, StringArray};
+use arrow::compute::kernels::cmp::gt;
+use arrow::compute::{cast, filter_record_batch};
+use arrow::record_batch::RecordBatch;
+use tiberius::time::time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+use tiberius::{Client, ColumnData, FromSql};
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+
+use crate::error::LakeApi2SqlError;
+
+/// Column used for incremental loads of append-only sources
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    /// Name of the column in the target table and in the arrow stream
+    pub column: String,
+    /// Name of the query string parameter that receives the current watermark
+    pub query_param: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatermarkResult {
+    pub column: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+fn format_date(d: Date) -> String {
+    format!("{:04}-{:02}-{:02}", d.year(), u8::from(d.month()), d.day())
+}
+
+fn format_time(t: Time) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:09}",
+        t.hour(),
+        t.minute(),
+        t.second(),
+        t.nanosecond()
+    )
+}
+
+/// Formats a watermark value in a way both arrow's cast kernel and the usual http apis can parse
+fn watermark_to_string(val: &ColumnData<'static>) -> Result<Option<String>, LakeApi2SqlError> {
+    Ok(match val {
+        ColumnData::U8(o) => o.map(|x| x.to_string()),
+        ColumnData::I16(o) => o.map(|x| x.to_string()),
+        ColumnData::I32(o) => o.map(|x| x.to_string()),
+        ColumnData::I64(o) => o.map(|x| x.to_string()),
+        ColumnData::F32(o) => o.map(|x| x.to_string()),
+        ColumnData::F64(o) => o.map(|x| x.to_string()),
+        ColumnData::Numeric(o) => o.map(|x| x.to_string()),
+        ColumnData::String(o) => o.as_ref().map(|x| x.clone().into_owned()),
+        ColumnData::Date(_) => Date::from_sql(val)?.map(format_date),
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            PrimitiveDateTime::from_sql(val)?
+                .map(|x| format!("{}T{}", format_date(x.date()), format_time(x.time())))
+        }
+        ColumnData::DateTimeOffset(_) => OffsetDateTime::from_sql(val)?.map(|x| {
+            let (h, m, _) = x.offset().as_hms();
+            format!(
+                "{}T{}{}{:02}:{:02}",
+                format_date(x.date()),
+                format_time(x.time()),
+                if x.offset().is_negative() { '-' } else { '+' },
+                h.abs(),
+                m.abs()
+            )
+        }),
+        other => {
+            return Err(LakeApi2SqlError::UnsupportedWatermark(format!(
+                "{:?}",
+                other
+            )))
+        }
+    })
+}
+
+/// Server error number for an invalid column name
+const INVALID_COLUMN_NAME: u32 = 207;
+
+/// Reads the current maximum of the watermark column from the target table
+pub async fn get_watermark(
+    db_client: &mut Client<Compat<TcpStream>>,
+    table_name: &str,
+    column: &str,
+) -> Result<Option<String>, LakeApi2SqlError> {
+    let query = format!("SELECT MAX([{}]) FROM {}", column, table_name);
+    let row = async { db_client.simple_query(query).await?.into_row().await }
+        .await
+        .map_err(|e| match e {
+            tiberius::error::Error::Server(t) if t.code() == INVALID_COLUMN_NAME => {
+                LakeApi2SqlError::WatermarkColumnNotFound(format!("{column} in {table_name}"))
+            }
+            e => e.into(),
+        })?;
+    match row {
+        Some(r) => match r.cells().next() {
+            Some((_, val)) => watermark_to_string(val),
+            None => Ok(None),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Removes all rows at or below the watermark. Rows without a value in the watermark column are removed as well
+pub(crate) fn filter_batch(
+    batch: RecordBatch,
+    column: &str,
+    watermark: &str,
+) -> Result<RecordBatch, LakeApi2SqlError> {
+    let col = batch
+        .column_by_name(column)
+        .ok_or_else(|| LakeApi2SqlError::WatermarkColumnNotFound(column.to_owned()))?;
+    let wm = cast(&StringArray::from(vec![watermark]), col.data_type())?;
+    let mask = gt(col, &Scalar::new(wm))?;
+    Ok(filter_record_batch(&batch, &mask)?)
+}
@@ -93,3 +93,79 @@ def spawn_sql():
 async def connection(spawn_sql):
     async with DB_Connection() as c:
         yield c
+
+
+class HttpSource:
+    """Serves arrow data over http, routes map a path to a function taking the query and the headers
+    and returning (status, headers, body). The body is bytes or an iterable of bytes"""
+
+    def __init__(self):
+        import threading
+        from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+        from urllib.parse import urlsplit, parse_qs
+
+        source = self
+        self.routes = {}
+        self.requests = []
+
+        class Handler(BaseHTTPRequestHandler):
+            def do_GET(self):
+                parts = urlsplit(self.path)
+                query = {k: v[0] for k, v in parse_qs(parts.query).items()}
+                body = self._read_body()
+                # the headers stay a case insensitive Message, clients like hyper send lower case header names
+                source.requests.append((parts.path, query, self.headers, self.command, body))
+                status, headers, body = source.routes[parts.path](query, self.headers)
+                self.send_response(status)
+                for k, v in headers.items():
+                    self.send_header(k, v)
+                if isinstance(body, bytes):
+                    self.send_header("Content-Length", str(len(body)))
+                    body = [body]
+                self.end_headers()
+                # a route can return the body in chunks, with a longer Content-Length to simulate a dropped connection
+                for chunk in body:
+                    self.wfile.write(chunk)
+                    self.wfile.flush()
+
+            do_POST = do_GET
+            do_PUT = do_GET
+
+            def _read_body(self) -> bytes:
+                if self.headers.get("Transfer-Encoding", "").lower() != "chunked":
+                    length = int(self.headers.get("Content-Length", 0))
+                    return self.rfile.read(length) if length else b""
+                chunks = []
+                while True:
+                    size = int(self.rfile.readline().split(b";")[0].strip(), 16)
+                    if size == 0:
+                        self.rfile.readline()
+                        return b"".join(chunks)
+                    chunks.append(self.rfile.read(size))
+                    self.rfile.readline()
+
+        self._server = ThreadingHTTPServer(("127.0.0.1", 0), Handler)
+        self._thread = threading.Thread(target=self._server.serve_forever, daemon=True)
+        self._thread.start()
+
+    def url(self, path: str) -> str:
+        return f"http://127.0.0.1:{self._server.server_port}{path}"
+
+    def stop(self):
+        self._server.shutdown()
+
+
+def arrow_stream_bytes(table, **ipc_options) -> bytes:
+    import pyarrow as pa
+
+    sink = pa.BufferOutputStream()
+    with pa.ipc.new_stream(sink, table.schema, options=pa.ipc.IpcWriteOptions(**ipc_options)) as writer:
+        writer.write_table(table, max_chunksize=10)
+    return sink.getvalue().to_pybytes()
+
+
+@pytest.fixture(scope="session")
+def http_source():
+    source = HttpSource()
+    yield source
+    source.stop()
@@ -0,0 +1,208 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+@pytest.mark.asyncio
+async def test_insert_fire_triggers(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batch = pa.record_batch([pa.array([1, 2, 3])], names=["f0"])
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_trigger;drop table if exists dbo.test_trigger_log;"
+            "create table dbo.test_trigger(f0 bigint);create table dbo.test_trigger_log(f0 bigint)"
+        )
+        await con.execute_sql(
+            "create trigger dbo.tr_test_trigger on dbo.test_trigger after insert as "
+            "insert into dbo.test_trigger_log(f0) select f0 from inserted"
+        )
+
+    await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_trigger",
+        pa.RecordBatchReader.from_batches(batch.schema, [batch]),
+        ["f0"],
+        bulk_options={"fire_triggers": True, "table_lock": False, "order_hints": ["f0 ASC"]},
+    )
+    await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_trigger",
+        pa.RecordBatchReader.from_batches(batch.schema, [batch]),
+        ["f0"],
+    )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*) from dbo.test_trigger")
+        assert res["rows"] == [(6,)]
+        res = await con.execute_sql_with_result("select f0 from dbo.test_trigger_log order by f0")
+        assert res["rows"] == [(1,), (2,), (3,)]
+
+
+@pytest.mark.asyncio
+async def test_insert_batch_rows(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [pa.record_batch([pa.array(list(range(s, s + n)))], names=["f0"]) for s, n in [(0, 1), (1, 2), (3, 4)]]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_batch_rows;create table dbo.test_batch_rows(f0 bigint)")
+
+    progress = []
+    res = await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_batch_rows",
+        pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+        ["f0"],
+        bulk_options={"batch_rows": 3},
+        progress_callback=progress.append,
+    )
+    # 1 + 2 rows are concatenated, 4 rows are sliced and the last row is sent on its own
+    assert res["batches"] == 3
+    assert [p["rows_written"] for p in progress] == [3, 6, 7]
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select f0 from dbo.test_batch_rows order by f0")
+        assert res["rows"] == [(i,) for i in range(7)]
+
+
+@pytest.mark.asyncio
+async def test_insert_parallel(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [pa.record_batch([pa.array(list(range(s, s + 10)))], names=["f0"]) for s in range(0, 100, 10)]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_parallel;create table dbo.test_parallel(f0 bigint)")
+
+    await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_parallel",
+        pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+        ["f0"],
+        bulk_options={"parallelism": 3, "table_lock": False},
+    )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select f0 from dbo.test_parallel order by f0")
+        assert res["rows"] == [(i,) for i in range(100)]
+    with pytest.raises(ValueError, match="parallelism"):
+        await insert_record_batch_to_sql(
+            connection.conn_str,
+            "dbo.test_parallel",
+            pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+            ["f0"],
+            bulk_options={"parallelism": 0},
+        )
+
+
+@pytest.mark.asyncio
+async def test_insert_pipelined(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [
+        pa.record_batch(
+            [pa.array(list(range(s, s + 10))), pa.array([f"v{i}" for i in range(s, s + 10)])], names=["f0", "f1"]
+        )
+        for s in range(0, 50, 10)
+    ]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_pipelined;create table dbo.test_pipelined(f0 bigint, f1 nvarchar(100))"
+        )
+
+    for bulk_options in [{"pipeline_depth": 0}, {"pipeline_depth": 2, "conversion_threads": 2}]:
+        await insert_record_batch_to_sql(
+            connection.conn_str,
+            "dbo.test_pipelined",
+            pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+            ["f0", "f1"],
+            bulk_options=bulk_options,  # type: ignore
+        )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select f0, f1 from dbo.test_pipelined order by f0, f1")
+        assert res["rows"] == [(i, f"v{i}") for i in range(50) for _ in range(2)]
+
+
+@pytest.mark.asyncio
+async def test_load_result(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [pa.record_batch([pa.array(list(range(s, s + 10)))], names=["f0"]) for s in range(0, 30, 10)]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_load_result;create table dbo.test_load_result(f0 bigint, f1 int)"
+        )
+
+    res = await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_load_result",
+        pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+        ["f0", "f1"],
+        bulk_options={"batch_rows": 20},
+    )
+    assert res["rows_read"] == 30
+    assert res["rows_written"] == 30
+    assert res["batches"] == 2
+    assert res["bytes_received"] == 0
+    assert [(c["name"], c["arrow_type"]) for c in res["columns"]] == [("f0", "Int64"), ("f1", None)]
+
+
+@pytest.mark.asyncio
+async def test_progress_callback(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [pa.record_batch([pa.array(list(range(s, s + 10)))], names=["f0"]) for s in range(0, 30, 10)]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_progress;create table dbo.test_progress(f0 bigint)")
+
+    progress = []
+    await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_progress",
+        pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+        ["f0"],
+        progress_callback=progress.append,
+    )
+    assert [(p["rows_written"], p["batches"]) for p in progress] == [(10, 1), (20, 2), (30, 3)]
+    assert all(p["elapsed_seconds"] >= 0 for p in progress)
+
+
+@pytest.mark.asyncio
+async def test_retry_batch(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batches = [pa.record_batch([pa.array(list(range(s, s + 10)))], names=["f0"]) for s in range(0, 30, 10)]
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_retry;drop sequence if exists dbo.seq_retry;"
+            "create table dbo.test_retry(f0 bigint);create sequence dbo.seq_retry start with 1"
+        )
+        # sequences are not rolled back, so only the first insert of the second batch fails
+        await con.execute_sql(
+            "create trigger dbo.tr_test_retry on dbo.test_retry after insert as "
+            "if (next value for dbo.seq_retry) = 2 throw 50001, 'transient', 1"
+        )
+
+    res = await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_retry",
+        pa.RecordBatchReader.from_batches(batches[0].schema, batches),
+        ["f0"],
+        bulk_options={"fire_triggers": True, "retry_error_codes": [50001], "retry_backoff": 0.1},
+    )
+    assert res["rows_written"] == 30
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select f0 from dbo.test_retry order by f0")
+        assert res["rows"] == [(i,) for i in range(30)]
@@ -0,0 +1,110 @@
+from typing import TYPE_CHECKING
+import asyncio
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+@pytest.mark.asyncio
+async def test_cancel_query(connection: "DB_Connection"):
+    async with connection.new_connection() as con:
+        task = asyncio.create_task(con.execute_sql("waitfor delay '00:00:30'"))
+        await asyncio.sleep(1)
+        task.cancel()
+        with pytest.raises(asyncio.CancelledError):
+            await task
+        res = await con.execute_sql_with_result("select 1")
+        assert res["rows"] == [(1,)]
+
+
+@pytest.mark.asyncio
+async def test_cancel_insert(connection: "DB_Connection"):
+    import time
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    schema = pa.schema([("f0", pa.int64())])
+
+    def slow_batches():
+        for s in range(0, 100, 10):
+            time.sleep(0.3)
+            yield pa.record_batch([pa.array(list(range(s, s + 10)))], schema=schema)
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_cancel;create table dbo.test_cancel(f0 bigint)")
+
+    task = asyncio.create_task(
+        insert_record_batch_to_sql(
+            connection.conn_str,
+            "dbo.test_cancel",
+            pa.RecordBatchReader.from_batches(schema, slow_batches()),
+            ["f0"],
+        )
+    )
+    await asyncio.sleep(1)
+    task.cancel()
+    with pytest.raises(asyncio.CancelledError):
+        await task
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*) from dbo.test_cancel")
+        # only whole batches are committed
+        assert res["rows"][0][0] % 10 == 0
+        assert res["rows"][0][0] < 100
+
+
+@pytest.mark.asyncio
+async def test_command_timeout(connection: "DB_Connection"):
+    from lakeapi2sql import TdsConnection
+
+    async with TdsConnection(connection.conn_str + ";Command Timeout=1") as con:
+        with pytest.raises(TimeoutError):
+            await con.execute_sql("waitfor delay '00:00:10'")
+        res = await con.execute_sql_with_result("select 1")
+        assert res["rows"] == [(1,)]
+
+
+@pytest.mark.asyncio
+async def test_load_timeout(connection: "DB_Connection"):
+    import time
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    schema = pa.schema([("f0", pa.int64())])
+
+    def slow_batches():
+        for s in range(0, 100, 10):
+            time.sleep(0.3)
+            yield pa.record_batch([pa.array(list(range(s, s + 10)))], schema=schema)
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_timeout;create table dbo.test_timeout(f0 bigint)")
+
+    with pytest.raises(TimeoutError):
+        await insert_record_batch_to_sql(
+            connection.conn_str,
+            "dbo.test_timeout",
+            pa.RecordBatchReader.from_batches(schema, slow_batches()),
+            ["f0"],
+            bulk_options={"load_timeout": 1},
+        )
+
+
+@pytest.mark.asyncio
+async def test_unread_results(connection: "DB_Connection", tmp_path):
+    async with connection.new_connection() as con:
+        # only the first result set is returned, the others must not be left for the next query
+        res = await con.execute_sql_with_result("select 1 as a; select 2 as b; select 3 as c")
+        assert res["rows"] == [(1,)]
+        res = await con.execute_sql_with_result("select 4")
+        assert res["rows"] == [(4,)]
+
+        # the writer fails on the first batch while the rest of the result is still on the wire
+        with pytest.raises(IOError, match="needs quoting"):
+            await con.export_query_to_csv(
+                "select top 100000 N'a,b' as txt from sys.all_objects a cross join sys.all_objects b",
+                str(tmp_path / "unread.csv"),
+                options={"quoting": "never", "batch_rows": 100},
+            )
+        res = await con.execute_sql_with_result("select 5")
+        assert res["rows"] == [(5,)]
@@ -0,0 +1,70 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+@pytest.mark.asyncio
+async def test_copy_table_create(connection: "DB_Connection"):
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_copy_src;drop table if exists dbo.test_copy_dst;"
+            "create table dbo.test_copy_src(id int identity primary key, "
+            "name nvarchar(20) collate Latin1_General_CS_AS, amount decimal(18,4), created datetime not null, "
+            "changed datetime2(3), seen datetimeoffset(3), "
+            "born date, at time(3), uid uniqueidentifier, active bit, small tinyint, data varbinary(10))"
+        )
+        await con.execute_sql(
+            "insert into dbo.test_copy_src(name, amount, created, changed, seen, born, at, uid, active, small, data) "
+            "values (N'Grüße', 12.3456, '1955-03-04T05:06:07.003', '2024-01-02T03:04:05.678', "
+            "'2024-01-02T03:04:05.678+02:00', '1900-01-01', '23:59:59.999', newid(), 1, 255, 0x0102), "
+            "(null, null, '2024-01-01', null, null, null, null, null, null, null, null)"
+        )
+        res = await con.copy_table("dbo.test_copy_src", connection.conn_str, "dbo.test_copy_dst", create_table=True)
+        assert res["rows_written"] == 2
+
+        columns = await con.execute_sql_with_result(
+            "select c.name, t.name, c.is_nullable, c.collation_name from sys.columns c "
+            "join sys.types t on t.user_type_id = c.user_type_id "
+            "where c.object_id = object_id('dbo.test_copy_dst') order by c.column_id"
+        )
+        assert columns["rows"][:4] == [
+            ("id", "int", False, None),
+            ("name", "nvarchar", True, "Latin1_General_CS_AS"),
+            ("amount", "decimal", True, None),
+            ("created", "datetime", False, None),
+        ]
+        diff = await con.execute_sql_with_result(
+            "select count(*) from (select * from dbo.test_copy_src except select * from dbo.test_copy_dst) d"
+        )
+        assert diff["rows"] == [(0,)]
+
+        # the table exists now, so this appends
+        res = await con.copy_table("dbo.test_copy_src", con, "dbo.test_copy_dst", create_table=True)
+        assert res["rows_written"] == 2
+
+
+@pytest.mark.asyncio
+async def test_copy_query(connection: "DB_Connection"):
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_copy_nrs;create table dbo.test_copy_nrs(nr bigint)")
+        res = await con.copy_query(
+            "select top (@P1) row_number() over (order by (select null)) as nr "
+            "from sys.all_columns a, sys.all_columns b",
+            connection.conn_str,
+            "dbo.test_copy_nrs",
+            arguments=[25000],
+            bulk_options={"batch_rows": 10000},
+        )
+        assert res["rows_read"] == 25000
+        assert res["rows_written"] == 25000
+        assert res["batches"] == 3
+        # the source connection is still usable
+        total = await con.execute_sql_with_result("select sum(nr) from dbo.test_copy_nrs")
+        assert total["rows"] == [(25000 * 25001 // 2,)]
+
+        with pytest.raises(ValueError, match="create_table"):
+            await con.copy_query(
+                "select @P1 as nr", connection.conn_str, "dbo.test_copy_nrs", arguments=[1], create_table=True
+            )
@@ -0,0 +1,203 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+@pytest.mark.asyncio
+async def test_delta_time_travel(connection: "DB_Connection"):
+    from datetime import datetime, timezone
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta;create table dbo.test_delta(num int, letter nchar(1))"
+        )
+
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta", "tests/data/delta-table")
+    assert res["rows_written"] == 6
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta", "tests/data/delta-table", version=0)
+    assert res["rows_written"] == 3
+    # between the two commits
+    res = await insert_delta_to_sql(
+        connection.conn_str,
+        "dbo.test_delta",
+        "tests/data/delta-table",
+        timestamp=datetime.fromtimestamp(1698613100, timezone.utc),
+    )
+    assert res["rows_written"] == 3
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select num, count(*) from dbo.test_delta group by num order by num")
+        assert res["rows"] == [(1, 3), (2, 3), (3, 3), (77, 1), (88, 1), (99, 1)]
+
+    with pytest.raises(IOError, match="version 5 does not exist"):
+        await insert_delta_to_sql(connection.conn_str, "dbo.test_delta", "tests/data/delta-table", version=5)
+
+
+@pytest.mark.asyncio
+async def test_delta_types(connection: "DB_Connection"):
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta_user;create table dbo.test_delta_user([User_-_iD] bigint, "
+            "FirstName nvarchar(100), LastName nvarchar(100), Age decimal(15,3), companyid varchar(10), "
+            "time_stamp bigint, __timestamp datetime2, __is_deleted bit, __is_full_load bit)"
+        )
+
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_user", "tests/data/user2")
+    assert res["rows_written"] == 6
+    assert [c["arrow_type"] for c in res["columns"]][3] == "Decimal128(15, 3)"
+
+
+@pytest.mark.asyncio
+async def test_delta_partitions(connection: "DB_Connection", tmp_path):
+    import json
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    schema = {
+        "type": "struct",
+        "fields": [
+            {"name": "id", "type": "long", "nullable": True, "metadata": {}},
+            {"name": "region", "type": "string", "nullable": True, "metadata": {}},
+            {"name": "day", "type": "date", "nullable": True, "metadata": {}},
+        ],
+    }
+    files = {
+        "region=east/day=2024-01-01/part-0.parquet": ({"region": "east", "day": "2024-01-01"}, [1, 2]),
+        "region=west%20coast/day=2024-01-02/part-1.parquet": ({"region": "west coast", "day": "2024-01-02"}, [3]),
+        "region=null/day=2024-01-03/part-2.parquet": ({"region": None, "day": "2024-01-03"}, [4]),
+    }
+    for path, (_, ids) in files.items():
+        local = tmp_path / path.replace("%20", " ")
+        local.parent.mkdir(parents=True)
+        pq.write_table(pa.table({"id": pa.array(ids, pa.int64())}), local)
+    adds = [
+        {"add": {"path": p, "partitionValues": v, "size": 0, "modificationTime": 0, "dataChange": True}}
+        for p, (v, _) in files.items()
+    ]
+    log = tmp_path / "_delta_log"
+    log.mkdir()
+    commit0 = [
+        {"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}},
+        {
+            "metaData": {
+                "id": "1",
+                "format": {"provider": "parquet", "options": {}},
+                "schemaString": json.dumps(schema),
+                "partitionColumns": ["region", "day"],
+                "configuration": {},
+            }
+        },
+    ] + adds
+    commit1 = [{"remove": {"path": list(files.keys())[0], "dataChange": True}}]
+    (log / "00000000000000000000.json").write_text("\n".join(json.dumps(a) for a in commit0))
+    (log / "00000000000000000001.json").write_text("\n".join(json.dumps(a) for a in commit1))
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta_part;"
+            "create table dbo.test_delta_part(id bigint, region nvarchar(20), day date)"
+        )
+
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_part", str(tmp_path))
+    assert res["rows_written"] == 2
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(
+            "select id, region, convert(varchar(10), day, 23) from dbo.test_delta_part order by id"
+        )
+        assert res["rows"] == [(3, "west coast", "2024-01-02"), (4, None, "2024-01-03")]
+
+
+@pytest.mark.asyncio
+async def test_export_delta_append(connection: "DB_Connection", tmp_path):
+    import json
+    import shutil
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    table = tmp_path / "delta-table"
+    shutil.copytree("tests/data/delta-table", table)
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_delta(
+            "select cast(num as int) as num, cast(letter as nchar(1)) as letter "
+            "from (values (5, 'x'), (6, null)) v(num, letter)",
+            str(table),
+        )
+        assert res["rows"] == 2
+        assert len(res["files"]) == 1
+        commit = [json.loads(line) for line in (table / "_delta_log" / "00000000000000000002.json").open()]
+        assert commit[0]["commitInfo"]["operationParameters"]["mode"] == "Append"
+        assert [a["add"]["path"] for a in commit[1:]] == [res["files"][0].rsplit("/", 1)[-1]]
+
+        with pytest.raises(IOError, match="the query returns the columns"):
+            await con.export_query_to_delta("select 1 as other", str(table))
+
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta_export;create table dbo.test_delta_export(num int, letter nchar(1))"
+        )
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_export", str(table))
+    assert res["rows_written"] == 8
+
+
+@pytest.mark.asyncio
+async def test_export_delta_partitioned(connection: "DB_Connection", tmp_path):
+    import json
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    table = tmp_path / "parts"
+    query = (
+        "select id, region, cast('2024-01-01T10:00:00' as datetime2) as ts from (values "
+        "(1, N'east'), (2, N'west coast'), (3, null), (4, N'east')) v(id, region)"
+    )
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_delta(query, str(table), options={"partition_by": ["region"]})
+        assert res["rows"] == 4
+        assert sorted(f.rsplit("/", 2)[-2] for f in res["files"]) == [
+            "region=__HIVE_DEFAULT_PARTITION__",
+            "region=east",
+            "region=west coast",
+        ]
+        commit = [json.loads(line) for line in (table / "_delta_log" / "00000000000000000000.json").open()]
+        assert commit[1]["protocol"]["readerFeatures"] == ["timestampNtz"]
+        assert commit[2]["metaData"]["partitionColumns"] == ["region"]
+        adds = sorted(a["add"]["path"] for a in commit[3:])
+        assert adds[2].startswith("region=west%20coast/")
+
+        res = await con.export_query_to_delta(
+            "select 5 as id, N'north' as region, cast('2024-01-02' as datetime2) as ts",
+            str(table),
+            options={"mode": "overwrite"},
+        )
+        assert res["rows"] == 1
+
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta_export_part;"
+            "create table dbo.test_delta_export_part(id int, region nvarchar(20), ts datetime2)"
+        )
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_export_part", str(table))
+    assert res["rows_written"] == 1
+    res = await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_export_part", str(table), version=0)
+    assert res["rows_written"] == 4
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select id, region from dbo.test_delta_export_part order by id")
+        assert res["rows"] == [(1, "east"), (2, "west coast"), (3, None), (4, "east"), (5, "north")]
+
+
+@pytest.mark.asyncio
+async def test_delta_missing_file(connection: "DB_Connection", tmp_path):
+    import shutil
+    from lakeapi2sql.bulk_insert import insert_delta_to_sql
+
+    shutil.copytree("tests/data/delta-table", tmp_path / "delta-table")
+    # added by the second commit and still active
+    (tmp_path / "delta-table" / "part-00000-1a7ca5ae-c5c6-49e8-b3cc-5554600b8447-c000.snappy.parquet").unlink()
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_delta_missing;create table dbo.test_delta_missing(num int, letter nchar(1))"
+        )
+
+    with pytest.raises(IOError, match="NotFound"):
+        await insert_delta_to_sql(connection.conn_str, "dbo.test_delta_missing", str(tmp_path / "delta-table"))
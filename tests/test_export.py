@@ -0,0 +1,214 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection, HttpSource
+
+TYPES_QUERY = (
+    "select cast(1.5 as decimal(10,2)) as dec, cast('2023-05-01T12:30:00.1234567' as datetime2) as dt2, "
+    "cast('2023-05-01T12:30:00+02:00' as datetimeoffset) as dto, cast('2023-05-01' as date) as d, "
+    "cast('12:30:15' as time) as t, cast('6F9619FF-8B86-D011-B42D-00C04FC964FF' as uniqueidentifier) as id, "
+    "cast(5 as tinyint) as tiny, N'täst' as txt, cast(null as int) as nothing"
+)
+
+
+@pytest.mark.asyncio
+async def test_export_parquet_types(connection: "DB_Connection", tmp_path):
+    import uuid
+    from datetime import date, datetime, time, timezone
+    from decimal import Decimal
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+
+    path = str(tmp_path / "types.parquet")
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_parquet(TYPES_QUERY, path, options={"compression": "zstd"})
+    assert res["rows"] == 1
+    assert res["files"] == [path]
+
+    schema = pq.read_schema(path)
+    assert schema.field("dec").type == pa.decimal128(38, 2)
+    assert schema.field("dt2").type == pa.timestamp("us")
+    assert schema.field("dto").type == pa.timestamp("us", tz="UTC")
+    assert schema.field("d").type == pa.date32()
+    assert schema.field("t").type == pa.time64("us")
+    assert schema.field("tiny").type == pa.uint8()
+    assert schema.field("txt").type == pa.string()
+    parquet_schema = pq.ParquetFile(path).schema
+    assert str(parquet_schema.column(5).logical_type) == "UUID"
+    assert pq.ParquetFile(path).metadata.row_group(0).column(0).compression == "ZSTD"
+
+    row = pq.read_table(path).to_pylist()[0]
+    assert row["dec"] == Decimal("1.50")
+    assert row["dt2"] == datetime(2023, 5, 1, 12, 30, 0, 123456)
+    assert row["dto"] == datetime(2023, 5, 1, 10, 30, tzinfo=timezone.utc)
+    assert row["d"] == date(2023, 5, 1)
+    assert row["t"] == time(12, 30, 15)
+    assert uuid.UUID(bytes=bytes(row["id"])) == uuid.UUID("6F9619FF-8B86-D011-B42D-00C04FC964FF")
+    assert row["txt"] == "täst"
+    assert row["nothing"] is None
+
+
+@pytest.mark.asyncio
+async def test_export_parquet_split(connection: "DB_Connection", tmp_path):
+    import pyarrow.parquet as pq
+
+    query = "select top 2500 row_number() over (order by (select null)) as nr from sys.all_columns"
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_parquet(
+            query,
+            str(tmp_path / "nrs.parquet"),
+            options={"batch_rows": 300, "row_group_rows": 500, "max_rows_per_file": 1000},
+        )
+        assert res["rows"] == 2500
+        assert [f.rsplit("/", 1)[-1] for f in res["files"]] == [
+            "nrs-00000.parquet",
+            "nrs-00001.parquet",
+            "nrs-00002.parquet",
+        ]
+        assert [pq.ParquetFile(f).metadata.num_rows for f in res["files"]] == [1000, 1000, 500]
+        assert pq.ParquetFile(res["files"][0]).metadata.num_row_groups == 2
+        assert pq.read_table(str(tmp_path)).column("nr").to_pylist() == list(range(1, 2501))
+
+        res = await con.export_query_to_parquet("select 1 as a where 1 = 0", str(tmp_path / "empty.parquet"))
+        assert res["rows"] == 0
+        assert pq.read_table(res["files"][0]).num_rows == 0
+
+        with pytest.raises(ValueError, match="unknown compression"):
+            await con.export_query_to_parquet(query, str(tmp_path / "x.parquet"), options={"compression": "lzo"})
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("fmt,compression", [("arrow_stream", "lz4"), ("arrow_file", "zstd"), ("arrow_stream", None)])
+async def test_export_arrow_file(connection: "DB_Connection", tmp_path, fmt, compression):
+    import pyarrow as pa
+
+    path = str(tmp_path / "types.arrow")
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_arrow(
+            TYPES_QUERY, path, options={"format": fmt, "compression": compression, "batch_rows": 10}
+        )
+    assert res["rows"] == 1
+    assert res["files"] == [path]
+    with open(path, "rb") as f:
+        data = f.read()
+    assert res["bytes"] == len(data)
+    table = pa.ipc.open_stream(data).read_all() if fmt == "arrow_stream" else pa.ipc.open_file(data).read_all()
+    assert table.schema.field("dto").type == pa.timestamp("us", tz="UTC")
+    # newer pyarrow versions read it as uuid extension type
+    id_type = table.schema.field("id").type
+    assert getattr(id_type, "storage_type", id_type) == pa.binary(16)
+    assert table.column("txt").to_pylist() == ["täst"]
+
+
+@pytest.mark.asyncio
+async def test_export_arrow_http(connection: "DB_Connection", http_source: "HttpSource"):
+    import pyarrow as pa
+
+    http_source.routes["/upload"] = lambda query, headers: (201, {}, b"")
+    http_source.routes["/denied"] = lambda query, headers: (403, {}, b"")
+    query = (
+        "select top 20000 row_number() over (order by (select null)) as nr "
+        "from sys.all_columns a, sys.all_columns b"
+    )
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_arrow(
+            query,
+            http_source.url("/upload"),
+            options={"batch_rows": 1000},
+            http_auth={"bearer_token": "secret"},
+        )
+        assert res["rows"] == 20000
+        assert res["files"] == []
+        path, _, headers, method, body = http_source.requests[-1]
+        assert (path, method) == ("/upload", "PUT")
+        assert headers["Authorization"] == "Bearer secret"
+        assert headers["Content-Type"] == "application/vnd.apache.arrow.stream"
+        assert res["bytes"] == len(body)
+        assert pa.ipc.open_stream(body).read_all().column("nr").to_pylist() == list(range(1, 20001))
+
+        await con.export_query_to_arrow(
+            "select 1 as a", http_source.url("/upload"), http_request={"method": "POST"}
+        )
+        assert http_source.requests[-1][3] == "POST"
+
+        with pytest.raises(IOError, match="403"):
+            await con.export_query_to_arrow(query, http_source.url("/denied"))
+        # the connection is still usable
+        assert (await con.execute_sql_with_result("select 1 as a"))["rows"] == [(1,)]
+
+
+@pytest.mark.asyncio
+async def test_export_csv_types(connection: "DB_Connection", tmp_path):
+    path = tmp_path / "types.csv"
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_csv(TYPES_QUERY, str(path))
+    assert res["rows"] == 1
+    assert res["files"] == [str(path)]
+    content = path.read_bytes()
+    assert res["bytes"] == len(content)
+    assert content.decode("utf-8").split("\r\n") == [
+        "dec,dt2,dto,d,t,id,tiny,txt,nothing",
+        "1.50,2023-05-01 12:30:00.123456,2023-05-01 10:30:00 +00:00,2023-05-01,12:30:15,"
+        "6f9619ff-8b86-d011-b42d-00c04fc964ff,5,täst,",
+        "",
+    ]
+
+
+@pytest.mark.asyncio
+async def test_export_csv_quoting(connection: "DB_Connection", tmp_path):
+    query = (
+        "select id, txt, flag from (values (1, N'a,b', cast(1 as bit)), (2, N'say \"hi\"', cast(0 as bit)), "
+        "(3, N'', null), (4, null, null)) v(id, txt, flag) order by id"
+    )
+    path = tmp_path / "quoted.csv"
+    async with connection.new_connection() as con:
+        await con.export_query_to_csv(query, str(path), options={"header": False, "row_terminator": "\n"})
+        assert path.read_text("utf-8") == '1,"a,b",1\n2,"say ""hi""",0\n3,"",\n4,,\n'
+
+        await con.export_query_to_csv(query, str(path), options={"quoting": "all", "null": "NULL"})
+        assert path.read_text("utf-8").split("\r\n")[3:5] == ['"3","",NULL', '"4",NULL,NULL']
+
+        with pytest.raises(IOError, match="needs quoting"):
+            await con.export_query_to_csv(query, str(path), options={"quoting": "none"})
+
+
+@pytest.mark.asyncio
+async def test_export_csv_bcp(connection: "DB_Connection", tmp_path):
+    query = (
+        "select 1 as id, N'Grüße' as [full name], cast('2024-02-03T04:05:06.500' as datetime) as ts, "
+        "cast(null as date) as d"
+    )
+    path = tmp_path / "bcp.txt"
+    options = {
+        "delimiter": "\t",
+        "quoting": "none",
+        "null": "",
+        "header": False,
+        "datetime_format": "%Y-%m-%d %H:%M:%S%.3f",
+        "encoding": 1252,
+        "format_file": True,
+    }
+    async with connection.new_connection() as con:
+        res = await con.export_query_to_csv(query, str(path), options=options)
+        assert res["files"] == [str(tmp_path / "bcp.fmt"), str(path)]
+        assert path.read_bytes() == "1\tGrüße\t2024-02-03 04:05:06.500\t\r\n".encode("cp1252")
+        assert (tmp_path / "bcp.fmt").read_text().splitlines() == [
+            "10.0",
+            "4",
+            '1\tSQLCHAR\t0\t0\t"\\t"\t1\tid\t""',
+            '2\tSQLCHAR\t0\t0\t"\\t"\t2\tfull_name\t""',
+            '3\tSQLCHAR\t0\t0\t"\\t"\t3\tts\t""',
+            '4\tSQLCHAR\t0\t0\t"\\r\\n"\t4\td\t""',
+        ]
+
+        res = await con.export_query_to_csv(
+            query, str(path), options={"encoding": "utf-16", "format_file": str(tmp_path / "wide.fmt")}
+        )
+        assert path.read_bytes().startswith(b"\xff\xfe" + "id,full name,".encode("utf-16-le"))
+        assert "SQLNCHAR" in (tmp_path / "wide.fmt").read_text()
+
+        with pytest.raises(IOError, match="cannot be written in windows-1252"):
+            await con.export_query_to_csv("select N'日本' as txt", str(path), options={"encoding": 1252})
+        with pytest.raises(ValueError, match="code page 437"):
+            await con.export_query_to_csv(query, str(path), options={"encoding": 437})
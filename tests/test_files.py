@@ -0,0 +1,117 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+async def _create_table(connection: "DB_Connection", name: str):
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            f"drop table if exists dbo.{name};"
+            f"create table dbo.{name}(f0 bigint, f1 nvarchar(10), source nvarchar(500))"
+        )
+
+
+@pytest.mark.asyncio
+async def test_parquet_files(connection: "DB_Connection", tmp_path):
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+    from lakeapi2sql.bulk_insert import insert_files_to_sql
+
+    for i in range(3):
+        table = pa.table({"f0": list(range(i * 10, i * 10 + 10)), "f1": [f"v{i}"] * 10, "f2": [1.5] * 10})
+        pq.write_table(table, tmp_path / f"part-{i}.parquet", row_group_size=4)
+    await _create_table(connection, "test_parquet_files")
+
+    res = await insert_files_to_sql(
+        connection.conn_str,
+        "dbo.test_parquet_files",
+        str(tmp_path / "*.parquet"),
+        file_options={"columns": ["f1", "f0"], "filename_column": "source"},
+    )
+    assert res["rows_written"] == 30
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(
+            "select count(*), count(distinct f0), count(distinct source) from dbo.test_parquet_files"
+        )
+        assert res["rows"] == [(30, 30, 3)]
+        res = await con.execute_sql_with_result("select top 1 source from dbo.test_parquet_files where f0 = 25")
+        assert res["rows"][0][0].endswith("part-2.parquet")
+
+
+@pytest.mark.asyncio
+async def test_ipc_files(connection: "DB_Connection", tmp_path):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_files_to_sql
+
+    table = pa.table({"f0": list(range(10)), "f1": [f"v{i}" for i in range(10)]})
+    with pa.ipc.new_file(str(tmp_path / "data.arrow"), table.schema) as writer:
+        writer.write_table(table)
+    with pa.ipc.new_stream(str(tmp_path / "data.arrows"), table.schema) as writer:
+        writer.write_table(table)
+    await _create_table(connection, "test_ipc_files")
+
+    for name in ["data.arrow", "data.arrows"]:
+        res = await insert_files_to_sql(connection.conn_str, "dbo.test_ipc_files", str(tmp_path / name), ["f0", "f1"])
+        assert res["rows_written"] == 10
+
+
+@pytest.mark.asyncio
+async def test_csv_and_ndjson_files(connection: "DB_Connection", tmp_path):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_files_to_sql
+
+    (tmp_path / "a.csv").write_text("f0;f1\n1;'a;b'\n2;\n")
+    (tmp_path / "b.csv").write_text("f0;f1\n3;c\n")
+    (tmp_path / "c.txt").write_text("4,d\n5,e\n")
+    (tmp_path / "d.jsonl").write_text('{"f0": 6, "f1": "f"}\n{"f0": 7}\n')
+    await _create_table(connection, "test_csv_files")
+
+    res = await insert_files_to_sql(
+        connection.conn_str,
+        "dbo.test_csv_files",
+        str(tmp_path / "*.csv"),
+        ["f0", "f1"],
+        file_options={"csv_delimiter": ";", "csv_quote": "'"},
+    )
+    assert res["rows_written"] == 3
+    res = await insert_files_to_sql(
+        connection.conn_str,
+        "dbo.test_csv_files",
+        str(tmp_path / "c.txt"),
+        ["f0", "f1"],
+        file_options={
+            "format": "csv",
+            "csv_has_header": False,
+            "schema": pa.schema([("f0", pa.int64()), ("f1", pa.string())]),
+        },
+    )
+    assert res["rows_written"] == 2
+    res = await insert_files_to_sql(connection.conn_str, "dbo.test_csv_files", str(tmp_path / "d.jsonl"), ["f0", "f1"])
+    assert res["rows_written"] == 2
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select f0, f1 from dbo.test_csv_files order by f0")
+        assert res["rows"] == [(1, "a;b"), (2, None), (3, "c"), (4, "d"), (5, "e"), (6, "f"), (7, None)]
+
+    with pytest.raises(ValueError, match="no files match"):
+        await insert_files_to_sql(connection.conn_str, "dbo.test_csv_files", str(tmp_path / "*.parquet"))
+    for name in ["e.json", "f.tsv"]:
+        (tmp_path / name).write_text("[]")
+        with pytest.raises(ValueError, match="cannot detect the format"):
+            await insert_files_to_sql(connection.conn_str, "dbo.test_csv_files", str(tmp_path / name))
+
+
+@pytest.mark.asyncio
+async def test_corrupt_file(connection: "DB_Connection", tmp_path):
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+    from lakeapi2sql.bulk_insert import insert_files_to_sql
+
+    for i in range(3):
+        pq.write_table(pa.table({"f0": list(range(i * 10, i * 10 + 10))}), tmp_path / f"part-{i}.parquet")
+    (tmp_path / "part-1.parquet").write_bytes(b"not a parquet file")
+    await _create_table(connection, "test_corrupt_file")
+
+    with pytest.raises(ValueError):
+        await insert_files_to_sql(connection.conn_str, "dbo.test_corrupt_file", str(tmp_path / "*.parquet"), ["f0"])
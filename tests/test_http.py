@@ -0,0 +1,484 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection, HttpSource
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("skip_param", [None, "skip"])
+async def test_resume_from_checkpoint(
+    connection: "DB_Connection", http_source: "HttpSource", tmp_path, skip_param: str | None
+):
+    import time
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    table = pa.table({"f0": list(range(30))})
+    # schema and the first two batches, without the end of stream marker
+    two_batches = len(arrow_stream_bytes(table.slice(0, 20))) - 8
+    state = {"interrupt": True}
+
+    def resume(query, headers):
+        body = arrow_stream_bytes(table.slice(int(query.get("skip", 0))))
+        if not state["interrupt"]:
+            return (200, {"Content-Type": "application/vnd.apache.arrow.stream"}, body)
+
+        def interrupted():
+            yield body[:two_batches]
+            # gives the load time to commit the two batches
+            time.sleep(2)
+            # the connection drops in the middle of the third batch
+            yield body[two_batches : two_batches + 20]
+
+        return (
+            200,
+            {"Content-Type": "application/vnd.apache.arrow.stream", "Content-Length": str(len(body))},
+            interrupted(),
+        )
+
+    route = f"/resume_{skip_param}"
+    http_source.routes[route] = resume
+    table_name = f"dbo.test_resume_{skip_param}"
+    async with connection.new_connection() as con:
+        await con.execute_sql(f"drop table if exists {table_name};create table {table_name}(f0 bigint)")
+
+    checkpoint = tmp_path / "resume.checkpoint"
+    with pytest.raises(IOError):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            table_name,
+            http_source.url(route),
+            ("user", "pwd"),
+            col_names=["f0"],
+            checkpoint_file=str(checkpoint),
+            checkpoint_skip_param=skip_param,
+        )
+    assert checkpoint.read_text() == "rows=20\n"
+
+    state["interrupt"] = False
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        table_name,
+        http_source.url(route),
+        ("user", "pwd"),
+        col_names=["f0"],
+        checkpoint_file=str(checkpoint),
+        checkpoint_skip_param=skip_param,
+    )
+    if skip_param:
+        assert http_source.requests[-1][1]["skip"] == "20"
+        assert res["rows_read"] == 10
+    else:
+        assert "skip" not in http_source.requests[-1][1]
+        assert res["rows_read"] == 30
+    assert res["rows_skipped"] == 20
+    assert res["rows_written"] == 10
+    assert not checkpoint.exists()
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(f"select count(*), count(distinct f0) from {table_name}")
+        assert res["rows"] == [(30, 30)]
+
+
+@pytest.mark.asyncio
+async def test_watermark(connection: "DB_Connection", http_source: "HttpSource"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    source = {"rows": 30}
+
+    # ignores the watermark parameter, so the rows at or below it have to be filtered while loading
+    def rows(query, headers):
+        ids = list(range(source["rows"]))
+        return (200, {}, arrow_stream_bytes(pa.table({"id": ids, "v": [f"v{i}" for i in ids]})))
+
+    http_source.routes["/watermark"] = rows
+    http_source.routes["/no_watermark"] = lambda query, headers: (
+        200,
+        {},
+        arrow_stream_bytes(pa.table({"v": ["x"]})),
+    )
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_watermark;create table dbo.test_watermark(id bigint, v nvarchar(10))"
+        )
+
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_watermark",
+        http_source.url("/watermark"),
+        None,
+        watermark_column="id",
+    )
+    assert res["rows_written"] == 30
+    assert res["watermark"] == {"column": "id", "old": None, "new": "29"}
+    assert "id" not in http_source.requests[-1][1]
+
+    source["rows"] = 40
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_watermark",
+        http_source.url("/watermark"),
+        None,
+        watermark_column="id",
+        watermark_param="since",
+    )
+    assert http_source.requests[-1][1]["since"] == "29"
+    assert res["rows_read"] == 40
+    assert res["rows_written"] == 10
+    assert res["watermark"] == {"column": "id", "old": "29", "new": "39"}
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*), count(distinct id) from dbo.test_watermark")
+        assert res["rows"] == [(40, 40)]
+
+    # neither in the target table ...
+    with pytest.raises(ValueError, match="WatermarkColumnNotFound"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_watermark",
+            http_source.url("/watermark"),
+            None,
+            watermark_column="nope",
+        )
+    # ... nor in the data
+    with pytest.raises(ValueError, match="WatermarkColumnNotFound"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_watermark",
+            http_source.url("/no_watermark"),
+            None,
+            watermark_column="id",
+        )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*) from dbo.test_watermark")
+        assert res["rows"] == [(40,)]
+
+
+@pytest.mark.asyncio
+async def test_http_auth(connection: "DB_Connection", http_source: "HttpSource"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    body = arrow_stream_bytes(pa.table({"f0": list(range(5))}))
+
+    def authorized(query, headers):
+        ok = headers.get("Authorization") == "Bearer secret" or query.get("key") == "secret"
+        return (200, {}, body) if ok else (401, {}, b"")
+
+    http_source.routes["/auth"] = authorized
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_http_auth;create table dbo.test_http_auth(f0 bigint)")
+
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_auth",
+        http_source.url("/auth"),
+        None,
+        col_names=["f0"],
+        http_auth={"bearer_token": "secret", "headers": {"X-Test": "1"}},
+    )
+    assert res["rows_written"] == 5
+    assert http_source.requests[-1][2]["X-Test"] == "1"
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_auth",
+        http_source.url("/auth"),
+        None,
+        col_names=["f0"],
+        http_auth={"api_key": "secret", "api_key_param": "key"},
+    )
+    assert res["rows_written"] == 5
+    http_source.routes["/token"] = lambda query, headers: (
+        200,
+        {"Content-Type": "application/json"},
+        b'{"access_token": "secret", "expires_in": 3600}',
+    )
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_auth",
+        http_source.url("/auth"),
+        None,
+        col_names=["f0"],
+        http_auth={
+            "oauth2_token_url": http_source.url("/token"),
+            "oauth2_client_id": "id",
+            "oauth2_client_secret": "secret",
+        },
+    )
+    assert res["rows_written"] == 5
+    assert http_source.requests[-2][0] == "/token"
+
+    with pytest.raises(PermissionError, match="401"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str, "dbo.test_http_auth", http_source.url("/auth"), ("user", "wrong"), col_names=["f0"]
+        )
+    # the token endpoint rejects the client credentials
+    http_source.routes["/token_denied"] = lambda query, headers: (401, {}, b"")
+    with pytest.raises(PermissionError, match="token_denied"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_http_auth",
+            http_source.url("/auth"),
+            None,
+            col_names=["f0"],
+            http_auth={
+                "oauth2_token_url": http_source.url("/token_denied"),
+                "oauth2_client_id": "id",
+                "oauth2_client_secret": "wrong",
+            },
+        )
+    http_source.routes["/token_invalid"] = lambda query, headers: (200, {}, b'{"error": "nope"}')
+    with pytest.raises(PermissionError, match="no access_token"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_http_auth",
+            http_source.url("/auth"),
+            None,
+            col_names=["f0"],
+            http_auth={
+                "oauth2_token_url": http_source.url("/token_invalid"),
+                "oauth2_client_id": "id",
+                "oauth2_client_secret": "secret",
+            },
+        )
+    with pytest.raises(ValueError, match="oauth2_client_secret is required"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_http_auth",
+            http_source.url("/auth"),
+            None,
+            col_names=["f0"],
+            http_auth={"oauth2_token_url": http_source.url("/token"), "oauth2_client_id": "id"},
+        )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*) from dbo.test_http_auth")
+        assert res["rows"] == [(15,)]
+
+
+@pytest.mark.asyncio
+async def test_http_request(connection: "DB_Connection", http_source: "HttpSource"):
+    import json
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    body = arrow_stream_bytes(pa.table({"f0": list(range(5))}))
+    http_source.routes["/request"] = lambda query, headers: (200, {}, body)
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_http_request;create table dbo.test_http_request(f0 bigint)"
+        )
+
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_request",
+        http_source.url("/request"),
+        None,
+        col_names=["f0"],
+        http_request={
+            "method": "POST",
+            "params": {"limit": 5},
+            "headers": {"Accept": "application/vnd.apache.arrow.stream"},
+            "json": {"filter": ["a", "b"]},
+        },
+    )
+    assert res["rows_written"] == 5
+    path, query, headers, method, req_body = http_source.requests[-1]
+    assert method == "POST"
+    assert query == {"limit": "5"}
+    assert headers["Accept"] == "application/vnd.apache.arrow.stream"
+    assert headers["Content-Type"] == "application/json"
+    assert json.loads(req_body) == {"filter": ["a", "b"]}
+
+    # None means no body, like for the other options
+    await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_request",
+        http_source.url("/request"),
+        None,
+        col_names=["f0"],
+        http_request={"json": None},
+    )
+    path, query, headers, method, req_body = http_source.requests[-1]
+    assert method == "GET"
+    assert "Content-Type" not in headers
+    assert req_body == b""
+
+
+@pytest.mark.asyncio
+async def test_http_formats(connection: "DB_Connection", http_source: "HttpSource"):
+    import io
+    import json
+    import pyarrow as pa
+    import pyarrow.parquet as pq
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+
+    table = pa.table({"f0": list(range(25)), "f1": [f"v{i}" for i in range(25)]})
+    ipc_file = pa.BufferOutputStream()
+    with pa.ipc.new_file(ipc_file, table.schema) as writer:
+        writer.write_table(table, max_chunksize=10)
+    parquet_file = io.BytesIO()
+    pq.write_table(table, parquet_file, row_group_size=10)
+    ndjson = "\n".join(json.dumps(r) for r in table.to_pylist()).encode()
+    bodies = {
+        "application/vnd.apache.arrow.file": ipc_file.getvalue().to_pybytes(),
+        "application/vnd.apache.parquet": parquet_file.getvalue(),
+        "application/x-ndjson; charset=utf-8": ndjson,
+    }
+    http_source.routes["/format"] = lambda query, headers: (
+        200,
+        {"Content-Type": query["ct"]} if "ct" in query else {},
+        bodies[query.get("ct", "application/vnd.apache.parquet")],
+    )
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_http_format;create table dbo.test_http_format(f0 bigint, f1 nvarchar(10))"
+        )
+
+    for ct in bodies.keys():
+        res = await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_http_format",
+            http_source.url("/format"),
+            None,
+            col_names=["f0", "f1"],
+            http_request={"params": {"ct": ct}},
+        )
+        assert res["rows_written"] == 25
+    # without content type the format has to be given explicitly
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str,
+        "dbo.test_http_format",
+        http_source.url("/format"),
+        None,
+        col_names=["f0", "f1"],
+        http_request={"format": "parquet"},
+    )
+    assert res["rows_written"] == 25
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(
+            "select count(*), count(distinct f0), max(f1) from dbo.test_http_format"
+        )
+        assert res["rows"] == [(100, 25, "v9")]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("compression", ["lz4", "zstd"])
+async def test_compressed_ipc_stream(connection: "DB_Connection", http_source: "HttpSource", compression: str):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    table = pa.table({"f0": list(range(25)), "f1": [f"v{i}" for i in range(25)]})
+    body = arrow_stream_bytes(table, compression=compression)
+    http_source.routes["/ipc_" + compression] = lambda query, headers: (200, {}, body)
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            f"drop table if exists dbo.test_ipc_{compression};"
+            f"create table dbo.test_ipc_{compression}(f0 bigint, f1 nvarchar(10))"
+        )
+
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str, f"dbo.test_ipc_{compression}", http_source.url("/ipc_" + compression), None
+    )
+    assert res["rows_written"] == 25
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(f"select f0, f1 from dbo.test_ipc_{compression} order by f0")
+        assert res["rows"] == [(i, f"v{i}") for i in range(25)]
+
+
+@pytest.mark.asyncio
+@pytest.mark.parametrize("encoding", ["gzip", "br", "zstd"])
+async def test_content_encoding(connection: "DB_Connection", http_source: "HttpSource", encoding: str):
+    import gzip
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    body = arrow_stream_bytes(pa.table({"f0": list(range(25))}))
+    if encoding == "gzip":
+        body = gzip.compress(body)
+    else:
+        body = pa.compress(body, codec="brotli" if encoding == "br" else encoding, asbytes=True)
+    http_source.routes["/encoding_" + encoding] = lambda query, headers: (200, {"Content-Encoding": encoding}, body)
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            f"drop table if exists dbo.test_encoding_{encoding};create table dbo.test_encoding_{encoding}(f0 bigint)"
+        )
+
+    res = await insert_http_arrow_stream_to_sql(
+        connection.conn_str, f"dbo.test_encoding_{encoding}", http_source.url("/encoding_" + encoding), None
+    )
+    assert res["rows_written"] == 25
+    assert encoding in http_source.requests[-1][2]["Accept-Encoding"]
+
+
+@pytest.mark.asyncio
+async def test_pagination(connection: "DB_Connection", http_source: "HttpSource"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_http_arrow_stream_to_sql
+    from .conftest import arrow_stream_bytes
+
+    table = pa.table({"f0": list(range(25))})
+
+    def page(query, headers):
+        offset = int(query.get("offset", query.get("cursor", 0)))
+        limit = int(query.get("limit", 10))
+        next_headers = {}
+        if offset + limit < table.num_rows:
+            next_headers["Link"] = f'</page?offset={offset + limit}>; rel="next"'
+            next_headers["X-Cursor"] = str(offset + limit)
+        return (200, next_headers, arrow_stream_bytes(table.slice(offset, limit)))
+
+    http_source.routes["/page"] = page
+    http_source.routes["/other_schema"] = lambda query, headers: (
+        200,
+        {"Link": "</page?offset=10>; rel=next"},
+        arrow_stream_bytes(pa.table({"f0": pa.array([1], pa.int32())})),
+    )
+
+    async with connection.new_connection() as con:
+        await con.execute_sql("drop table if exists dbo.test_pagination;create table dbo.test_pagination(f0 bigint)")
+
+    for pagination in [
+        {"type": "next_link"},
+        {"type": "cursor", "header": "X-Cursor", "param": "cursor"},
+        {"type": "offset", "param": "offset", "limit_param": "limit", "page_size": 10},
+    ]:
+        requests = len(http_source.requests)
+        res = await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_pagination",
+            http_source.url("/page"),
+            None,
+            http_request={"pagination": pagination},  # type: ignore
+        )
+        assert res["rows_written"] == 25
+        assert len(http_source.requests) - requests == 3
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*), count(distinct f0) from dbo.test_pagination")
+        assert res["rows"] == [(75, 25)]
+
+    requests = len(http_source.requests)
+    with pytest.raises(ValueError, match="SchemaMismatch"):
+        await insert_http_arrow_stream_to_sql(
+            connection.conn_str,
+            "dbo.test_pagination",
+            http_source.url("/other_schema"),
+            None,
+            http_request={"pagination": {"type": "next_link"}},
+        )
+    assert len(http_source.requests) - requests == 2
+    # the second page has another schema, none of its rows are written
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select count(*) from dbo.test_pagination where f0 >= 10")
+        assert res["rows"] == [(45,)]
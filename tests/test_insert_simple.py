@@ -31,3 +31,54 @@ async def test_insert_simple(connection: "DB_Connection"):
         print(res["columns"])
         assert [c["name"] for c in res["columns"]] == ["f0", "f1", "f2"]
         assert res["rows"] == [(1, "foo", True), (2, "bar", None), (3, "$ä,àE", False), (4, None, True)]
+
+
+@pytest.mark.asyncio
+async def test_insert_unsupported_type(connection: "DB_Connection"):
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    batch = pa.record_batch([pa.array([1, 2], pa.int32()), pa.array([True, False])], names=["f0", "f1"])
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_unsupported;create table dbo.test_unsupported(f0 nvarchar(10), f1 int)"
+        )
+
+    for col in ["f0", "f1"]:
+        with pytest.raises(TypeError, match="NotSupported"):
+            await insert_record_batch_to_sql(
+                connection.conn_str,
+                "dbo.test_unsupported",
+                pa.RecordBatchReader.from_batches(batch.schema, [batch]),
+                [col],
+            )
+
+
+@pytest.mark.asyncio
+async def test_insert_timestamps_before_1970(connection: "DB_Connection"):
+    from datetime import datetime
+    import pyarrow as pa
+    from lakeapi2sql.bulk_insert import insert_record_batch_to_sql
+
+    values = [datetime(1950, 6, 15, 12, 30, 45, 123000), datetime(1969, 12, 31, 23, 59, 59, 999000), None]
+    batch = pa.record_batch(
+        [pa.array(values, pa.timestamp(unit)) for unit in ["ms", "us", "ns"]], names=["ms", "us", "ns"]
+    )
+
+    async with connection.new_connection() as con:
+        await con.execute_sql(
+            "drop table if exists dbo.test_before_1970;"
+            "create table dbo.test_before_1970(ms datetime2(3), us datetime2(6), ns datetime2(7))"
+        )
+
+    await insert_record_batch_to_sql(
+        connection.conn_str,
+        "dbo.test_before_1970",
+        pa.RecordBatchReader.from_batches(batch.schema, [batch]),
+        ["ms", "us", "ns"],
+    )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result("select ms, us, ns from dbo.test_before_1970 order by ms")
+        # nulls sort first
+        assert res["rows"] == [(None, None, None)] + [(v, v, v) for v in values[:2]]
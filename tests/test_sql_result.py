@@ -0,0 +1,44 @@
+from typing import TYPE_CHECKING
+import pytest
+
+if TYPE_CHECKING:
+    from .conftest import DB_Connection
+
+
+@pytest.mark.asyncio
+async def test_result_types(connection: "DB_Connection"):
+    import uuid
+    from datetime import date, datetime, time, timedelta, timezone
+    from decimal import Decimal
+
+    query = (
+        "select cast(1.5 as decimal(10,2)) as dec, cast('2023-05-01T12:30:00.1234567' as datetime2) as dt2, "
+        "cast('2023-05-01T12:30:00.5' as datetime) as dt, cast('2023-05-01T12:30:00' as smalldatetime) as sdt, "
+        "cast('2023-05-01T12:30:00+02:00' as datetimeoffset) as dto, cast('2023-05-01' as date) as d, "
+        "cast('12:30:15.25' as time) as t, cast('6F9619FF-8B86-D011-B42D-00C04FC964FF' as uniqueidentifier) as id, "
+        "cast(null as datetime2) as nothing, cast(null as decimal(10,2)) as no_dec"
+    )
+    async with connection.new_connection() as con:
+        res = await con.execute_sql_with_result(query)
+        row = res["rows"][0]
+        assert row == (
+            Decimal("1.50"),
+            datetime(2023, 5, 1, 12, 30, 0, 123456),
+            datetime(2023, 5, 1, 12, 30, 0, 500000),
+            datetime(2023, 5, 1, 12, 30),
+            datetime(2023, 5, 1, 12, 30, tzinfo=timezone(timedelta(hours=2))),
+            date(2023, 5, 1),
+            time(12, 30, 15, 250000),
+            uuid.UUID("6F9619FF-8B86-D011-B42D-00C04FC964FF"),
+            None,
+            None,
+        )
+        # the offset of the value is kept
+        assert row[4].utcoffset() == timedelta(hours=2)
+        assert str(row[0]) == "1.50"
+
+        res = await con.execute_sql_with_result(
+            "select 1 as a, @P1 as b union all select 2, null", ["x"], as_dict=True
+        )
+        assert [c["name"] for c in res["columns"]] == ["a", "b"]
+        assert res["rows"] == [{"a": 1, "b": "x"}, {"a": 2, "b": None}]